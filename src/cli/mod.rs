@@ -1,4 +1,6 @@
-use clap::{Parser, Subcommand};
+use crate::{InstallationReport, ManifestError, ReportFormat};
+use clap::{builder::BoolishValueParser, Args, Parser, Subcommand};
+use std::{path::PathBuf, process::ExitCode};
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
@@ -11,19 +13,218 @@ pub struct Cli {
 pub enum Commands {
     #[command(name = "install", about = "Install toolkits.")]
     Install(InstallOpts),
+    #[command(name = "manifest", about = "Inspect the toolkits manifest.")]
+    Manifest(ManifestOpts),
+    #[command(
+        name = "config",
+        about = "Read and write the user configuration in ~/.config/toolkit/config.toml."
+    )]
+    Config(ConfigOpts),
     // TODO: Implement the command
     // #[command(name = "list", about = "Show the toolkits Details.")]
     // List(ListOpts),
 }
 
+impl Commands {
+    /// The `--manifest` option of the command, if it reads a manifest.
+    pub fn manifest_mut(&mut self) -> Option<&mut Vec<String>> {
+        match self {
+            Commands::Install(opts) => Some(&mut opts.source.manifest),
+            Commands::Manifest(opts) => match &mut opts.command {
+                ManifestCommands::Resolve(opts) => Some(&mut opts.source.manifest),
+                ManifestCommands::Script(opts) => Some(&mut opts.source.manifest),
+            },
+            Commands::Config(_) => None,
+        }
+    }
+}
+
+pub const DEFAULT_MANIFEST: &str =
+    "https://raw.githubusercontent.com/apptools-lab/AppToolkit/feat/cli/toolkits.manifest.json";
+
+/// The default timeout of the install commands, 30 minutes.
+pub const DEFAULT_TIMEOUT_SECS: u64 = 1800;
+
+/// The options of the commands which read the toolkits manifest.
+#[derive(Args, Debug)]
+pub struct ManifestSourceOpts {
+    #[arg(
+        long,
+        env = "TOOLKIT_MANIFEST",
+        value_delimiter = ',',
+        default_value = DEFAULT_MANIFEST,
+        help = "Path to the toolkits manifest file. You can pass a http(s):// or file:// URL, a local file path, `-` for stdin or a git+https:// / git+file:// repository URL ending with `#<ref>:<path>`. Repeat it or separate the manifests with commas to layer several manifests in order."
+    )]
+    pub manifest: Vec<String>,
+    #[arg(
+        long,
+        env = "TOOLKIT_OFFLINE",
+        value_parser = BoolishValueParser::new(),
+        help = "Use the cached copies of remote manifests without fetching them."
+    )]
+    pub offline: bool,
+    #[arg(
+        long,
+        env = "TOOLKIT_INSECURE_SKIP_VERIFY",
+        value_parser = BoolishValueParser::new(),
+        help = "Use remote manifests without verifying their minisign signatures. Only use it with manifests you trust."
+    )]
+    pub insecure_skip_verify: bool,
+}
+
 #[derive(Parser, Debug)]
 pub struct InstallOpts {
+    #[command(flatten)]
+    pub source: ManifestSourceOpts,
+    #[arg(long, help = "Write a machine-readable installation report to the given path.")]
+    pub report: Option<PathBuf>,
+    #[arg(
+        long,
+        value_enum,
+        env = "TOOLKIT_REPORT_FORMAT",
+        default_value_t = ReportFormat::Json,
+        requires = "report",
+        help = "The format of the installation report."
+    )]
+    pub report_format: ReportFormat,
     #[arg(
         long,
-        default_value = "https://raw.githubusercontent.com/apptools-lab/AppToolkit/feat/cli/toolkits.manifest.json",
-        help = "Path to the toolkits manifest file. You can pass a URL to a remote manifest file or a file path to a local manifest file."
+        env = "TOOLKIT_FAIL_FAST",
+        value_parser = BoolishValueParser::new(),
+        help = "Abort the remaining installations after the first failure."
     )]
-    pub manifest: String,
+    pub fail_fast: bool,
+    #[arg(
+        long,
+        env = "TOOLKIT_JOBS",
+        help = "The maximum number of tools installed at the same time. Unlimited by default."
+    )]
+    pub jobs: Option<usize>,
+    #[arg(
+        long,
+        env = "TOOLKIT_TIMEOUT",
+        default_value_t = DEFAULT_TIMEOUT_SECS,
+        help = "The timeout in seconds of the install commands and steps which do not set their own `timeout`. A command which times out is killed with the processes it started. 0 disables it."
+    )]
+    pub timeout: u64,
+    #[arg(
+        long,
+        help = "Refuse to install anything which does not match the toolkit.lock written by a previous install."
+    )]
+    pub locked: bool,
+    #[arg(
+        long,
+        conflicts_with = "locked",
+        help = "Write the toolkit.lock in the current directory when the manifest is not a local file. The lockfile of a local manifest is always written next to it."
+    )]
+    pub write_lock: bool,
+    #[arg(
+        long,
+        help = "Keep the partial results of a failed installation for debugging instead of rolling them back."
+    )]
+    pub no_rollback: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct ManifestOpts {
+    #[command(subcommand)]
+    pub command: ManifestCommands,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ManifestCommands {
+    #[command(
+        name = "resolve",
+        about = "Print the manifest after merging its extends, includes and layers and expanding its variables."
+    )]
+    Resolve(ManifestResolveOpts),
+    #[command(
+        name = "script",
+        about = "Download a tool's pinned install script, verify its sha256 and print it for review."
+    )]
+    Script(ManifestScriptOpts),
+}
+
+#[derive(Parser, Debug)]
+pub struct ManifestResolveOpts {
+    #[command(flatten)]
+    pub source: ManifestSourceOpts,
+}
+
+#[derive(Parser, Debug)]
+pub struct ManifestScriptOpts {
+    #[arg(help = "The name of the tool.")]
+    pub name: String,
+    #[command(flatten)]
+    pub source: ManifestSourceOpts,
+}
+
+#[derive(Parser, Debug)]
+pub struct ConfigOpts {
+    #[command(subcommand)]
+    pub command: ConfigCommands,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommands {
+    #[command(name = "get", about = "Print the effective value of a config key.")]
+    Get {
+        #[arg(help = "The config key, e.g. `manifest`.")]
+        key: String,
+    },
+    #[command(name = "set", about = "Write a config key. Separate the items of a list by commas.")]
+    Set {
+        #[arg(help = "The config key, e.g. `manifest`.")]
+        key: String,
+        #[arg(help = "The value of the key.")]
+        value: String,
+    },
+    #[command(
+        name = "list",
+        about = "Print every config key with its effective value and where it comes from."
+    )]
+    List,
+}
+
+/// The exit status of the `toolkit` process.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ExitStatus {
+    /// Every tool is installed or was already installed.
+    Success = 0,
+    /// At least one tool failed to install or a manifest hook failed.
+    InstallationFailed = 1,
+    /// The command line arguments are invalid.
+    Usage = 2,
+    /// The toolkits manifest or the config file could not be loaded or is invalid.
+    ManifestError = 3,
+    /// The installation was interrupted by Ctrl-C.
+    Interrupted = 130,
+}
+
+impl ExitStatus {
+    pub fn from_report(report: &InstallationReport) -> Self {
+        if report.is_interrupted() {
+            ExitStatus::Interrupted
+        } else if report.failures() > 0 || report.hook_failures() > 0 {
+            ExitStatus::InstallationFailed
+        } else {
+            ExitStatus::Success
+        }
+    }
+
+    pub fn from_error(err: &anyhow::Error) -> Self {
+        if err.is::<ManifestError>() {
+            ExitStatus::ManifestError
+        } else {
+            ExitStatus::InstallationFailed
+        }
+    }
+}
+
+impl From<ExitStatus> for ExitCode {
+    fn from(status: ExitStatus) -> Self {
+        ExitCode::from(status as u8)
+    }
 }
 
 #[derive(Parser, Debug)]
@@ -33,3 +234,83 @@ pub struct GitSSHOpts {
     #[arg(long)]
     user_email: String,
 }
+
+#[cfg(test)]
+mod test_exit_status {
+    use super::*;
+    use crate::{ToolInstallationResult, ToolInstallationStatus};
+    use std::time::Duration;
+
+    fn create_report(statuses: &[ToolInstallationStatus]) -> InstallationReport {
+        let tools = statuses
+            .iter()
+            .map(|status| ToolInstallationResult::unfinished("tool", *status, "", Duration::ZERO))
+            .collect();
+        InstallationReport::new("manifest.json", Duration::ZERO, tools)
+    }
+
+    #[test]
+    fn test_from_report() {
+        use ToolInstallationStatus::*;
+        assert_eq!(
+            ExitStatus::from_report(&create_report(&[Installed, AlreadyInstalled])),
+            ExitStatus::Success
+        );
+        assert_eq!(
+            ExitStatus::from_report(&create_report(&[Installed, Failed, Cancelled])),
+            ExitStatus::InstallationFailed
+        );
+        assert_eq!(
+            ExitStatus::from_report(&create_report(&[Failed, Interrupted])),
+            ExitStatus::Interrupted
+        );
+    }
+
+    #[test]
+    fn test_from_error() {
+        let err = anyhow::Error::new(ManifestError::new("manifest.json", anyhow::anyhow!("missing field")));
+        assert_eq!(ExitStatus::from_error(&err), ExitStatus::ManifestError);
+        assert_eq!(
+            ExitStatus::from_error(&anyhow::anyhow!("disk full")),
+            ExitStatus::InstallationFailed
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_cli {
+    use super::*;
+
+    #[test]
+    fn test_split_manifests() {
+        // `TOOLKIT_MANIFEST` is split the same way, like the lists of the config file
+        let cli = Cli::try_parse_from(["toolkit", "install", "--manifest", "./team.yaml,./my-tools.yaml"]).unwrap();
+        let Some(Commands::Install(opts)) = cli.command else {
+            panic!("expected the install command");
+        };
+        assert_eq!(opts.source.manifest, vec!["./team.yaml", "./my-tools.yaml"]);
+    }
+
+    #[test]
+    fn test_shared_manifest_options() {
+        let cli = Cli::try_parse_from([
+            "toolkit",
+            "manifest",
+            "script",
+            "fnm",
+            "--offline",
+            "--insecure-skip-verify",
+        ])
+        .unwrap();
+        let Some(Commands::Manifest(ManifestOpts {
+            command: ManifestCommands::Script(opts),
+        })) = cli.command
+        else {
+            panic!("expected the manifest script command");
+        };
+        assert_eq!(opts.name, "fnm");
+        assert_eq!(opts.source.manifest, vec![DEFAULT_MANIFEST]);
+        assert!(opts.source.offline);
+        assert!(opts.source.insecure_skip_verify);
+    }
+}
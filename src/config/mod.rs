@@ -0,0 +1,394 @@
+use anyhow::Result;
+use clap::{Command, ValueEnum};
+use serde::{Deserialize, Serialize};
+use std::{
+    env,
+    fmt::{self, Display},
+    fs,
+    path::PathBuf,
+};
+use toml::{Table, Value};
+
+use crate::{utils::get_config_dir, ReportFormat, DEFAULT_MANIFEST};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum ValueKind {
+    String,
+    Bool,
+    Integer,
+    List,
+}
+
+/// A key of the user configuration with its `TOOLKIT_*` environment variable and built-in default.
+#[derive(Debug)]
+pub struct ConfigKey {
+    pub name: &'static str,
+    pub env: &'static str,
+    pub description: &'static str,
+    kind: ValueKind,
+    default: Option<&'static str>,
+}
+
+pub const CONFIG_KEYS: &[ConfigKey] = &[
+    ConfigKey {
+        name: "manifest",
+        env: "TOOLKIT_MANIFEST",
+        description: "The manifests to install from, layered in order.",
+        kind: ValueKind::List,
+        default: Some(DEFAULT_MANIFEST),
+    },
+    ConfigKey {
+        name: "offline",
+        env: "TOOLKIT_OFFLINE",
+        description: "Use the cached copies of remote manifests without fetching them.",
+        kind: ValueKind::Bool,
+        default: Some("false"),
+    },
+    ConfigKey {
+        name: "fail_fast",
+        env: "TOOLKIT_FAIL_FAST",
+        description: "Abort the remaining installations after the first failure.",
+        kind: ValueKind::Bool,
+        default: Some("false"),
+    },
+    ConfigKey {
+        name: "insecure_skip_verify",
+        env: "TOOLKIT_INSECURE_SKIP_VERIFY",
+        description: "Use remote manifests without verifying their signatures.",
+        kind: ValueKind::Bool,
+        default: Some("false"),
+    },
+    ConfigKey {
+        name: "report_format",
+        env: "TOOLKIT_REPORT_FORMAT",
+        description: "The format of the installation report: json, junit or markdown.",
+        kind: ValueKind::String,
+        default: Some("json"),
+    },
+    ConfigKey {
+        name: "jobs",
+        env: "TOOLKIT_JOBS",
+        description: "The maximum number of tools installed at the same time. Unlimited by default.",
+        kind: ValueKind::Integer,
+        default: None,
+    },
+    ConfigKey {
+        name: "timeout",
+        env: "TOOLKIT_TIMEOUT",
+        description: "The timeout in seconds of the install commands which do not set their own. 0 disables it.",
+        kind: ValueKind::Integer,
+        default: Some("1800"),
+    },
+    ConfigKey {
+        name: "proxy",
+        env: "TOOLKIT_PROXY",
+        description: "The proxy of the downloads and install commands, set as HTTP_PROXY and HTTPS_PROXY.",
+        kind: ValueKind::String,
+        default: None,
+    },
+    ConfigKey {
+        name: "prefix",
+        env: "TOOLKIT_PREFIX",
+        description: "The install prefix, available as `{{prefix}}` and `{{bin_dir}}` (`<prefix>/bin`) in manifests.",
+        kind: ValueKind::String,
+        default: Some("~/.local"),
+    },
+    ConfigKey {
+        name: "trusted_keys",
+        env: "TOOLKIT_TRUSTED_KEYS",
+        description: "The minisign public keys trusted to sign remote manifests.",
+        kind: ValueKind::List,
+        default: None,
+    },
+];
+
+/// The user configuration in `~/.config/toolkit/config.toml`.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    manifest: Option<Vec<String>>,
+    offline: Option<bool>,
+    fail_fast: Option<bool>,
+    insecure_skip_verify: Option<bool>,
+    report_format: Option<String>,
+    jobs: Option<usize>,
+    timeout: Option<u64>,
+    proxy: Option<String>,
+    prefix: Option<String>,
+    trusted_keys: Option<Vec<String>>,
+}
+
+/// Where the effective value of a config key comes from.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ConfigValueSource {
+    Env,
+    Config,
+    Default,
+}
+
+impl Display for ConfigValueSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigValueSource::Env => write!(f, "env"),
+            ConfigValueSource::Config => write!(f, "config"),
+            ConfigValueSource::Default => write!(f, "default"),
+        }
+    }
+}
+
+/// `$XDG_CONFIG_HOME/toolkit/config.toml` or `~/.config/toolkit/config.toml`.
+pub fn get_config_path() -> PathBuf {
+    get_config_dir().join("config.toml")
+}
+
+impl Config {
+    pub fn load() -> Result<Self> {
+        let table = load_config_table()?;
+        parse_config(table)
+    }
+
+    /// Uses the config values as the defaults of the command line options, so that the precedence is
+    /// CLI > `TOOLKIT_*` env > config > built-in default.
+    pub fn apply_defaults(&self, command: Command) -> Command {
+        let bool_value = |value: Option<bool>| value.map(|value| value.to_string());
+        let defaults = [
+            ("manifest", self.manifest.clone()),
+            ("offline", bool_value(self.offline).map(|value| vec![value])),
+            ("fail_fast", bool_value(self.fail_fast).map(|value| vec![value])),
+            (
+                "insecure_skip_verify",
+                bool_value(self.insecure_skip_verify).map(|value| vec![value]),
+            ),
+            ("report_format", self.report_format.clone().map(|value| vec![value])),
+            ("jobs", self.jobs.map(|value| vec![value.to_string()])),
+            ("timeout", self.timeout.map(|value| vec![value.to_string()])),
+        ];
+        apply_command_defaults(command, &defaults)
+    }
+
+    /// Exports the proxy as `HTTP_PROXY` and `HTTPS_PROXY` for the downloads and the install commands. Changing the
+    /// environment is only sound while no other thread reads it, so this is called before the runtime is started.
+    pub fn apply_proxy(&self) {
+        let proxy = env::var("TOOLKIT_PROXY").ok().or_else(|| self.proxy.clone());
+        if let Some(proxy) = proxy.filter(|proxy| !proxy.is_empty()) {
+            for name in ["HTTP_PROXY", "HTTPS_PROXY", "http_proxy", "https_proxy"] {
+                env::set_var(name, &proxy);
+            }
+        }
+    }
+
+    /// The install prefix, `~/.local` by default.
+    pub fn prefix(&self) -> PathBuf {
+        let prefix = env::var("TOOLKIT_PREFIX")
+            .ok()
+            .or_else(|| self.prefix.clone())
+            .unwrap_or_else(|| "~/.local".to_string());
+        match prefix.strip_prefix("~/") {
+            Some(path) => home::home_dir().unwrap_or_default().join(path),
+            None => PathBuf::from(prefix),
+        }
+    }
+
+    pub fn trusted_keys(&self) -> Vec<String> {
+        match env::var("TOOLKIT_TRUSTED_KEYS") {
+            Ok(keys) => split_list(&keys),
+            Err(_) => self.trusted_keys.clone().unwrap_or_default(),
+        }
+    }
+}
+
+fn apply_command_defaults(mut command: Command, defaults: &[(&str, Option<Vec<String>>)]) -> Command {
+    for (id, values) in defaults {
+        let Some(values) = values else {
+            continue;
+        };
+        if command.get_arguments().any(|arg| arg.get_id() == *id) {
+            let values = values.clone();
+            command = command.mut_arg(*id, |arg| arg.default_values(values));
+        }
+    }
+    let subcommands = command
+        .get_subcommands()
+        .map(|subcommand| subcommand.get_name().to_string())
+        .collect::<Vec<_>>();
+    for name in subcommands {
+        command = command.mut_subcommand(name, |subcommand| apply_command_defaults(subcommand, defaults));
+    }
+    command
+}
+
+fn load_config_table() -> Result<Table> {
+    let path = get_config_path();
+    if !path.exists() {
+        return Ok(Table::new());
+    }
+    let content = fs::read_to_string(&path)?;
+    content
+        .parse::<Table>()
+        .map_err(|err| anyhow::anyhow!("Invalid config '{}'. Error: {}", path.display(), err))
+}
+
+fn parse_config(table: Table) -> Result<Config> {
+    let config: Config = Value::Table(table)
+        .try_into()
+        .map_err(|err| anyhow::anyhow!("Invalid config '{}'. Error: {}", get_config_path().display(), err))?;
+    if let Some(report_format) = &config.report_format {
+        ReportFormat::from_str(report_format, true).map_err(|_| {
+            anyhow::anyhow!(
+                "Invalid config '{}'. Error: unknown report_format `{}`, expected `json`, `junit` or `markdown`",
+                get_config_path().display(),
+                report_format
+            )
+        })?;
+    }
+    Ok(config)
+}
+
+fn find_config_key(name: &str) -> Result<&'static ConfigKey> {
+    CONFIG_KEYS.iter().find(|key| key.name == name).ok_or_else(|| {
+        let names = CONFIG_KEYS.iter().map(|key| key.name).collect::<Vec<_>>();
+        anyhow::anyhow!("Unknown config key '{}'. Available keys: {}", name, names.join(", "))
+    })
+}
+
+fn parse_value(key: &ConfigKey, value: &str) -> Result<Value> {
+    match key.kind {
+        ValueKind::String => Ok(Value::String(value.to_string())),
+        ValueKind::Bool => match value.to_lowercase().as_str() {
+            "true" | "1" | "yes" | "on" => Ok(Value::Boolean(true)),
+            "false" | "0" | "no" | "off" => Ok(Value::Boolean(false)),
+            _ => Err(anyhow::anyhow!("'{}' expects true or false, got '{}'", key.name, value)),
+        },
+        ValueKind::Integer => value
+            .parse::<u32>()
+            .map(|value| Value::Integer(value.into()))
+            .map_err(|_| anyhow::anyhow!("'{}' expects a positive integer, got '{}'", key.name, value)),
+        ValueKind::List => Ok(Value::Array(split_list(value).into_iter().map(Value::String).collect())),
+    }
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(|item| item.to_string())
+        .collect()
+}
+
+/// Formats a value as it is accepted by `toolkit config set`.
+pub fn format_config_value(value: &Value) -> String {
+    match value {
+        Value::String(value) => value.clone(),
+        Value::Array(values) => values.iter().map(format_config_value).collect::<Vec<_>>().join(","),
+        value => value.to_string(),
+    }
+}
+
+/// Returns the effective value of a config key and where it comes from.
+pub fn get_config_value(name: &str) -> Result<(Option<Value>, ConfigValueSource)> {
+    let key = find_config_key(name)?;
+    let table = load_config_table()?;
+    resolve_config_value(key, &table)
+}
+
+fn resolve_config_value(key: &ConfigKey, table: &Table) -> Result<(Option<Value>, ConfigValueSource)> {
+    if let Ok(value) = env::var(key.env) {
+        return Ok((Some(parse_value(key, &value)?), ConfigValueSource::Env));
+    }
+    if let Some(value) = table.get(key.name) {
+        return Ok((Some(value.clone()), ConfigValueSource::Config));
+    }
+    let default = key.default.map(|value| parse_value(key, value)).transpose()?;
+    Ok((default, ConfigValueSource::Default))
+}
+
+/// Returns the effective values of every config key.
+pub fn list_config_values() -> Result<Vec<(&'static ConfigKey, Option<Value>, ConfigValueSource)>> {
+    let table = load_config_table()?;
+    CONFIG_KEYS
+        .iter()
+        .map(|key| {
+            let (value, source) = resolve_config_value(key, &table)?;
+            Ok((key, value, source))
+        })
+        .collect()
+}
+
+/// Validates the value and writes it to the config file. Lists are separated by commas.
+pub fn set_config_value(name: &str, value: &str) -> Result<()> {
+    let key = find_config_key(name)?;
+    let mut table = load_config_table()?;
+    table.insert(key.name.to_string(), parse_value(key, value)?);
+    parse_config(table.clone())?;
+
+    let path = get_config_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, toml::to_string_pretty(&table)?)
+        .map_err(|err| anyhow::anyhow!("Failed to write the config '{}'. Error: {}", path.display(), err))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test_config {
+    use super::*;
+    use crate::Cli;
+    use clap::{CommandFactory, FromArgMatches};
+
+    fn parse_cli(config: &Config, args: &[&str]) -> Cli {
+        let matches = config.apply_defaults(Cli::command()).get_matches_from(args);
+        Cli::from_arg_matches(&matches).unwrap()
+    }
+
+    #[test]
+    fn test_apply_config_defaults() {
+        let config = parse_config(
+            toml::from_str(
+                r#"
+manifest = ["./fixtures/toolkits.manifest.json", "./team.yaml"]
+fail_fast = true
+jobs = 2
+"#,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+        let Some(crate::Commands::Install(opts)) = parse_cli(&config, &["toolkit", "install"]).command else {
+            panic!("expected the install command");
+        };
+        assert_eq!(
+            opts.source.manifest,
+            vec!["./fixtures/toolkits.manifest.json", "./team.yaml"]
+        );
+        assert!(opts.fail_fast);
+        assert_eq!(opts.jobs, Some(2));
+
+        let Some(crate::Commands::Install(opts)) = parse_cli(
+            &config,
+            &["toolkit", "install", "--manifest", "other.json", "--jobs", "4"],
+        )
+        .command
+        else {
+            panic!("expected the install command");
+        };
+        assert_eq!(opts.source.manifest, vec!["other.json"]);
+        assert_eq!(opts.jobs, Some(4));
+    }
+
+    #[test]
+    fn test_parse_value() {
+        let key = find_config_key("manifest").unwrap();
+        assert_eq!(
+            parse_value(key, "a.json, b.yaml").unwrap(),
+            Value::Array(vec![Value::String("a.json".into()), Value::String("b.yaml".into())])
+        );
+        let key = find_config_key("offline").unwrap();
+        assert_eq!(parse_value(key, "yes").unwrap(), Value::Boolean(true));
+        assert!(parse_value(key, "maybe").is_err());
+        assert!(find_config_key("jbos").is_err());
+        assert!(parse_config(toml::from_str(r#"report_format = "xml""#).unwrap()).is_err());
+    }
+}
@@ -0,0 +1,159 @@
+use anyhow::Result;
+
+use crate::{installation::manifest_source::ManifestSource, ToolInstallationManifest, ToolkitsManifest};
+
+/// Returns the location of a manifest referenced by `extends` or `include`, relative to the referencing manifest.
+pub fn resolve_manifest_reference(manifest_path: &str, reference: &str) -> Result<String> {
+    ManifestSource::parse(manifest_path)?.join(reference)
+}
+
+/// Layers the overlay manifest on top of the base manifest.
+///
+/// The hooks set in the overlay replace the hooks of the base. Toolkits are merged by name: a new toolkit is appended, a toolkit with `"disabled": true` is removed, and
+/// otherwise the fields set in the overlay override the base. Installations are merged by `os`, `arch` and `type`:
+/// a matching installation is replaced and the others are appended.
+pub fn merge_manifests(mut base: ToolkitsManifest, overlay: ToolkitsManifest) -> ToolkitsManifest {
+    if !overlay.author.is_empty() {
+        base.author = overlay.author;
+    }
+    if !overlay.version.is_empty() {
+        base.version = overlay.version;
+    }
+    if !overlay.description.is_empty() {
+        base.description = overlay.description;
+    }
+    base.vars.extend(overlay.vars);
+    if overlay.hooks.before_all.is_some() {
+        base.hooks.before_all = overlay.hooks.before_all;
+    }
+    if overlay.hooks.after_all.is_some() {
+        base.hooks.after_all = overlay.hooks.after_all;
+    }
+
+    for tool in overlay.toolkits {
+        let position = base.toolkits.iter().position(|base_tool| base_tool.name == tool.name);
+        match position {
+            Some(index) if tool.disabled => {
+                base.toolkits.remove(index);
+            }
+            Some(index) => merge_toolkit(&mut base.toolkits[index], tool),
+            None if !tool.disabled => base.toolkits.push(tool),
+            None => {}
+        }
+    }
+
+    base
+}
+
+fn merge_toolkit(base: &mut ToolInstallationManifest, overlay: ToolInstallationManifest) {
+    if !overlay.description.is_empty() {
+        base.description = overlay.description;
+    }
+    if !overlay.depends_on.is_empty() {
+        base.depends_on = overlay.depends_on;
+    }
+    if !overlay.provides.is_empty() {
+        base.provides = overlay.provides;
+    }
+    if overlay.version.is_some() {
+        base.version = overlay.version;
+    }
+    base.vars.extend(overlay.vars);
+
+    for installation in overlay.installations {
+        let position = base.installations.iter().position(|base_installation| {
+            base_installation.os == installation.os
+                && base_installation.arch == installation.arch
+                && base_installation.r#type == installation.r#type
+        });
+        match position {
+            Some(index) => base.installations[index] = installation,
+            None => base.installations.push(installation),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_compose {
+    use super::*;
+    use crate::installation::toolkit_manifest::{resolve_toolkits_manifest, ManifestOptions};
+
+    #[test]
+    fn test_resolve_manifest_reference() -> Result<()> {
+        assert_eq!(
+            resolve_manifest_reference("https://example.com/manifests/team.json", "../base.json")?,
+            "https://example.com/base.json"
+        );
+        assert_eq!(
+            resolve_manifest_reference("./fixtures/compose/team.json", "base.json")?,
+            "./fixtures/compose/base.json"
+        );
+        assert_eq!(
+            resolve_manifest_reference("./fixtures/compose/team.json", "https://example.com/base.json")?,
+            "https://example.com/base.json"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_extends_and_include() -> Result<()> {
+        let manifest = resolve_toolkits_manifest(
+            &["./fixtures/compose/team.json".to_string()],
+            &ManifestOptions::default(),
+        )
+        .await?;
+        assert_eq!(manifest.author, "team");
+        assert_eq!(manifest.description, "The shared base manifest.");
+        let names = manifest
+            .toolkits
+            .iter()
+            .map(|tool| tool.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["Visual Studio Code", "fnm", "pnpm"]);
+
+        let vscode = &manifest.toolkits[0];
+        assert_eq!(vscode.installations.len(), 2);
+        assert_eq!(
+            vscode.installations[0].source,
+            "https://example.com/vscode/1.90.0/darwin"
+        );
+        assert_eq!(
+            vscode.installations[1].source,
+            "https://example.com/vscode/1.90.0/linux"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_layer_manifests() -> Result<()> {
+        let manifest = resolve_toolkits_manifest(
+            &[
+                "./fixtures/compose/base.json".to_string(),
+                "./fixtures/compose/tools.json".to_string(),
+            ],
+            &ManifestOptions::default(),
+        )
+        .await?;
+        let names = manifest
+            .toolkits
+            .iter()
+            .map(|tool| tool.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["Visual Studio Code", "Google Chrome", "fnm", "pnpm"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_detect_include_cycle() {
+        let err = resolve_toolkits_manifest(
+            &["./fixtures/compose/cycle-a.json".to_string()],
+            &ManifestOptions::default(),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            "Manifest include cycle detected: ./fixtures/compose/cycle-a.json -> ./fixtures/compose/cycle-b.json -> ./fixtures/compose/cycle-a.json"
+        );
+    }
+}
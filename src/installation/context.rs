@@ -0,0 +1,392 @@
+use anyhow::Result;
+use console::style;
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget};
+use lazy_static::lazy_static;
+use reqwest::Client;
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::Mutex as AsyncMutex;
+
+use crate::{
+    download_file_with_client, utils::get_cache_dir, Artifact, CommandOptions, CommandRunner, LockedTool,
+    RetriableError, SystemCommandRunner, Transaction, UndoAction,
+};
+
+lazy_static! {
+    /// Held while an interactive command runs, so that the prompts of two installations never share the terminal.
+    static ref INTERACTIVE_LOCK: AsyncMutex<()> = AsyncMutex::new(());
+    /// The directory of the logs of this run, so that a run never overwrites the logs of another one. The logs of the
+    /// older runs are pruned when it is created.
+    static ref RUN_LOG_DIR: PathBuf = {
+        let log_dir = get_log_dir();
+        prune_run_logs(&log_dir, KEPT_RUN_LOGS - 1);
+        let started_at = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        log_dir.join(format!("{}-{}", started_at, process::id()))
+    };
+}
+
+/// The number of runs whose logs are kept, including the current one.
+const KEPT_RUN_LOGS: usize = 20;
+
+/// The directory of the logs of the runs, `$XDG_CACHE_HOME/toolkit/logs` or `~/.cache/toolkit/logs`.
+fn get_log_dir() -> PathBuf {
+    get_cache_dir().join("logs")
+}
+
+/// Removes the logs of the runs but the `keep` latest ones. Pruning is best effort, like writing the logs.
+fn prune_run_logs(log_dir: &Path, keep: usize) {
+    let Ok(entries) = fs::read_dir(log_dir) else {
+        return;
+    };
+    // the directories of the runs are named `<timestamp>-<pid>`
+    let mut runs = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_dir()))
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let (started_at, pid) = name.split_once('-')?;
+            pid.parse::<u32>().ok()?;
+            Some((started_at.parse::<u64>().ok()?, entry.path()))
+        })
+        .collect::<Vec<_>>();
+    runs.sort_unstable_by(|a, b| b.cmp(a));
+    for (_, path) in runs.into_iter().skip(keep) {
+        let _ = fs::remove_dir_all(path);
+    }
+}
+
+/// Per-tool state shared with the platform installers: the progress bar, the tool log, the download counter, the
+/// locked installation of the tool, the undo actions of the installation, the runner of its commands and the client of
+/// its downloads.
+pub struct InstallContext {
+    name: String,
+    pb: ProgressBar,
+    progress: Option<MultiProgress>,
+    log_path: Option<PathBuf>,
+    log_file: Mutex<Option<File>>,
+    bytes_downloaded: AtomicU64,
+    locked_tool: Option<LockedTool>,
+    artifact: Mutex<Option<Artifact>>,
+    transaction: Mutex<Transaction>,
+    rollback: bool,
+    runner: Arc<dyn CommandRunner>,
+    http_client: Client,
+    timeout: Option<Duration>,
+    interactive: AtomicBool,
+}
+
+impl InstallContext {
+    pub fn new(name: &str, pb: ProgressBar) -> Self {
+        let (log_path, log_file) = create_log_file(name).unzip();
+        Self {
+            name: name.to_string(),
+            pb,
+            progress: None,
+            log_path,
+            log_file: Mutex::new(log_file),
+            bytes_downloaded: AtomicU64::new(0),
+            locked_tool: None,
+            artifact: Mutex::new(None),
+            transaction: Mutex::new(Transaction::default()),
+            rollback: true,
+            runner: Arc::new(SystemCommandRunner),
+            http_client: Client::new(),
+            timeout: None,
+            interactive: AtomicBool::new(false),
+        }
+    }
+
+    /// Runs the commands of the installation with the runner, e.g. a [`crate::FakeCommandRunner`] in tests.
+    pub fn with_runner(mut self, runner: Arc<dyn CommandRunner>) -> Self {
+        self.runner = runner;
+        self
+    }
+
+    /// Downloads the sources and scripts of the installation with the client, e.g. one which refuses the redirects
+    /// violating the source policy.
+    pub fn with_http_client(mut self, http_client: Client) -> Self {
+        self.http_client = http_client;
+        self
+    }
+
+    pub fn http_client(&self) -> &Client {
+        &self.http_client
+    }
+
+    /// Suspends the whole progress display, not only the progress bar of the tool, while an interactive command runs.
+    pub fn with_progress(mut self, progress: MultiProgress) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Keeps the partial results of a failed installation for debugging, with `--no-rollback`.
+    pub fn with_rollback(mut self, rollback: bool) -> Self {
+        self.rollback = rollback;
+        self
+    }
+
+    /// Enforces the installation of the tool locked in the lockfile, with `install --locked`.
+    pub fn with_locked_tool(mut self, locked_tool: Option<LockedTool>) -> Self {
+        self.locked_tool = locked_tool;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn pb(&self) -> &ProgressBar {
+        &self.pb
+    }
+
+    /// Kills the commands of the installation which run longer than the timeout, unless they set their own.
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Runs the commands of the installation in a pseudo-terminal, for the installation being tried.
+    pub fn set_interactive(&self, interactive: bool) {
+        self.interactive.store(interactive, Ordering::Relaxed);
+    }
+
+    pub fn is_interactive(&self) -> bool {
+        self.interactive.load(Ordering::Relaxed)
+    }
+
+    pub fn runner(&self) -> &dyn CommandRunner {
+        self.runner.as_ref()
+    }
+
+    pub fn log_path(&self) -> Option<&Path> {
+        self.log_path.as_deref()
+    }
+
+    pub fn bytes_downloaded(&self) -> u64 {
+        self.bytes_downloaded.load(Ordering::Relaxed)
+    }
+
+    pub fn set_message(&self, msg: &str) {
+        self.pb.set_message(format!("{}: {}", style(&self.name).bold(), msg));
+        self.log(msg);
+    }
+
+    pub fn log(&self, msg: &str) {
+        if let Some(file) = self.log_file.lock().unwrap().as_mut() {
+            let _ = writeln!(file, "{}", msg);
+        }
+    }
+
+    /// Runs the command and shows every line of its output as the progress message, or in a pseudo-terminal when
+    /// the installation is interactive.
+    pub async fn run_streaming(&self, program: &str) -> Result<()> {
+        let options = CommandOptions {
+            timeout: self.timeout,
+            ..Default::default()
+        };
+        if self.is_interactive() {
+            return self.run_interactive(program, &options).await;
+        }
+        self.runner
+            .run_streaming(program, &options, &mut |line| self.set_message(line))
+            .await
+    }
+
+    /// Runs the command in a pseudo-terminal with the progress display suspended, once the interactive commands
+    /// of the other installations finished. Its output is not in the tool log.
+    pub async fn run_interactive(&self, program: &str, options: &CommandOptions) -> Result<()> {
+        let _lock = match INTERACTIVE_LOCK.try_lock() {
+            Ok(lock) => lock,
+            Err(_) => {
+                self.set_message("Waiting for another interactive installation to finish...");
+                INTERACTIVE_LOCK.lock().await
+            }
+        };
+        self.log(&format!("$ {} (interactive)", program));
+        let _suspended = SuspendedProgress::new(self.progress.as_ref(), &self.pb);
+        eprintln!("{}: running `{}`", style(&self.name).bold(), program);
+        self.runner.run_interactive(program, options).await
+    }
+
+    pub fn locked_tool(&self) -> Option<&LockedTool> {
+        self.locked_tool.as_ref()
+    }
+
+    /// Downloads the source of the installation, or the locked url with `install --locked`, and records it for the
+    /// lockfile.
+    pub async fn download(&self, source: &str) -> Result<PathBuf> {
+        let url = self
+            .locked_tool
+            .as_ref()
+            .and_then(|locked_tool| locked_tool.url.as_deref())
+            .unwrap_or(source);
+        let downloaded_file = download_file_with_client(&self.http_client, url, |msg| self.set_message(msg))
+            .await
+            .map_err(RetriableError::wrap)?;
+        self.bytes_downloaded.fetch_add(downloaded_file.size, Ordering::Relaxed);
+        let artifact = Artifact {
+            url: downloaded_file.url,
+            sha256: downloaded_file.sha256,
+            size: downloaded_file.size,
+        };
+        if let Err(err) = self.record_artifact(artifact) {
+            let _ = fs::remove_file(&downloaded_file.path);
+            return Err(err);
+        }
+        Ok(downloaded_file.path)
+    }
+
+    /// Records the file downloaded by the installation, and checks it against the lockfile with `install --locked`.
+    pub fn record_artifact(&self, artifact: Artifact) -> Result<()> {
+        if let Some(locked_tool) = &self.locked_tool {
+            locked_tool.verify_artifact(&artifact)?;
+        }
+        *self.artifact.lock().unwrap() = Some(artifact);
+        Ok(())
+    }
+
+    pub fn take_artifact(&self) -> Option<Artifact> {
+        self.artifact.lock().unwrap().take()
+    }
+
+    pub fn register_undo(&self, action: UndoAction) {
+        self.transaction.lock().unwrap().register(action);
+    }
+
+    /// Moves an existing file or directory aside before the installation writes it, so that it is restored when the
+    /// installation fails.
+    pub fn prepare_path(&self, path: &Path) -> Result<()> {
+        self.transaction.lock().unwrap().prepare_path(path)
+    }
+
+    /// Reverts the changes of the failed installation, unless rollback is disabled.
+    pub async fn rollback(&self) -> Result<()> {
+        // the lock is not held while the undo commands run
+        let mut transaction = std::mem::take(&mut *self.transaction.lock().unwrap());
+        if self.rollback {
+            let options = CommandOptions {
+                timeout: self.timeout,
+                ..Default::default()
+            };
+            transaction.rollback(self.runner(), &options, |msg| self.log(msg)).await
+        } else {
+            for action in transaction.discard() {
+                self.log(&format!("Not rolled back (--no-rollback): {}", action));
+            }
+            Ok(())
+        }
+    }
+
+    /// Keeps the changes of the successful installation.
+    pub fn commit(&self) {
+        self.transaction.lock().unwrap().commit();
+    }
+}
+
+/// Hides the progress display until it is dropped, so that the output of an interactive command is not drawn over.
+struct SuspendedProgress {
+    progress: Option<MultiProgress>,
+    pb: Option<ProgressBar>,
+}
+
+impl SuspendedProgress {
+    fn new(progress: Option<&MultiProgress>, pb: &ProgressBar) -> Self {
+        // a hidden display, e.g. in tests, stays hidden
+        let progress = progress.filter(|progress| !progress.is_hidden()).cloned();
+        let pb = Some(pb).filter(|pb| progress.is_none() && !pb.is_hidden()).cloned();
+        if let Some(progress) = &progress {
+            let _ = progress.clear();
+            progress.set_draw_target(ProgressDrawTarget::hidden());
+        }
+        if let Some(pb) = &pb {
+            pb.set_draw_target(ProgressDrawTarget::hidden());
+        }
+        Self { progress, pb }
+    }
+}
+
+impl Drop for SuspendedProgress {
+    fn drop(&mut self) {
+        if let Some(progress) = &self.progress {
+            progress.set_draw_target(ProgressDrawTarget::stderr());
+        }
+        if let Some(pb) = &self.pb {
+            pb.set_draw_target(ProgressDrawTarget::stderr());
+        }
+    }
+}
+
+/// Creates the log of a tool or a hook in the directory of the run.
+fn create_log_file(name: &str) -> Option<(PathBuf, File)> {
+    fs::create_dir_all(&*RUN_LOG_DIR).ok()?;
+    let file_name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    // different names may share a file name, e.g. `Foo Bar` and `foo-bar`
+    for index in 1.. {
+        let path = match index {
+            1 => RUN_LOG_DIR.join(format!("{}.log", file_name)),
+            _ => RUN_LOG_DIR.join(format!("{}-{}.log", file_name, index)),
+        };
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => return Some((path, file)),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(_) => return None,
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod test_context {
+    use super::*;
+
+    #[test]
+    fn test_create_unique_log_files() {
+        let (first_path, _) = create_log_file("Test Log Tool").unwrap();
+        let (second_path, _) = create_log_file("test-log-tool").unwrap();
+        assert_ne!(first_path, second_path);
+        assert_eq!(first_path.parent(), Some(RUN_LOG_DIR.as_path()));
+        assert_eq!(second_path.parent(), Some(RUN_LOG_DIR.as_path()));
+        assert!(RUN_LOG_DIR.to_string_lossy().ends_with(&format!("-{}", process::id())));
+        assert_eq!(RUN_LOG_DIR.parent(), Some(get_log_dir().as_path()));
+    }
+
+    #[test]
+    fn test_prune_run_logs() {
+        let log_dir = tempfile::tempdir().unwrap();
+        for run in ["1700000000-12", "1700000300-7", "1700000100-3", "999999999-5"] {
+            fs::create_dir(log_dir.path().join(run)).unwrap();
+        }
+        fs::create_dir(log_dir.path().join("notes")).unwrap();
+
+        prune_run_logs(log_dir.path(), 2);
+
+        let mut names = fs::read_dir(log_dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, vec!["1700000100-3", "1700000300-7", "notes"]);
+    }
+}
@@ -0,0 +1,339 @@
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+
+use crate::{InstallationDetailItem, ToolInstallationManifest};
+
+/// A provider of a capability which is not installed because another provider of the capability was selected.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnselectedProvider {
+    pub name: String,
+    pub capability: String,
+    /// The name of the selected provider.
+    pub selected: String,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum VisitState {
+    Visiting,
+    Visited,
+}
+
+/// Checks that every `dependsOn` entry names a tool or a capability of the manifest and that the dependencies
+/// contain no cycle.
+pub fn validate_dependencies(tools_installation_manifest: &[ToolInstallationManifest]) -> Result<()> {
+    let tools: HashMap<&str, &ToolInstallationManifest> = tools_installation_manifest
+        .iter()
+        .map(|tool| (tool.name.as_str(), tool))
+        .collect();
+    let mut providers: HashMap<&str, Vec<&str>> = HashMap::new();
+    for tool in tools_installation_manifest {
+        for capability in &tool.provides {
+            providers
+                .entry(capability.as_str())
+                .or_default()
+                .push(tool.name.as_str());
+        }
+    }
+
+    // a dependency on a capability may be satisfied by any of its providers
+    let mut graph: HashMap<&str, Vec<&str>> = HashMap::new();
+    for tool in tools_installation_manifest {
+        let edges = graph.entry(tool.name.as_str()).or_default();
+        for dependency in &tool.depends_on {
+            if tools.contains_key(dependency.as_str()) {
+                edges.push(dependency.as_str());
+            } else if let Some(capability_providers) = providers.get(dependency.as_str()) {
+                edges.extend(capability_providers);
+            } else {
+                return Err(anyhow::anyhow!(
+                    "Tool '{}' depends on '{}', which is neither a tool nor a capability provided by a tool in the manifest",
+                    tool.name,
+                    dependency
+                ));
+            }
+        }
+    }
+
+    let mut states: HashMap<&str, VisitState> = HashMap::new();
+    for tool in tools_installation_manifest {
+        let mut path = vec![];
+        if let Some(cycle) = find_cycle(tool.name.as_str(), &graph, &mut states, &mut path) {
+            return Err(anyhow::anyhow!("Dependency cycle detected: {}", cycle.join(" -> ")));
+        }
+    }
+
+    Ok(())
+}
+
+fn find_cycle<'a>(
+    name: &'a str,
+    graph: &HashMap<&'a str, Vec<&'a str>>,
+    states: &mut HashMap<&'a str, VisitState>,
+    path: &mut Vec<&'a str>,
+) -> Option<Vec<&'a str>> {
+    match states.get(name) {
+        Some(VisitState::Visited) => return None,
+        Some(VisitState::Visiting) => {
+            let start = path.iter().position(|visiting| *visiting == name).unwrap_or(0);
+            let mut cycle = path[start..].to_vec();
+            cycle.push(name);
+            return Some(cycle);
+        }
+        None => {}
+    }
+
+    states.insert(name, VisitState::Visiting);
+    path.push(name);
+    for dependency in &graph[name] {
+        if let Some(cycle) = find_cycle(dependency, graph, states, path) {
+            return Some(cycle);
+        }
+    }
+    path.pop();
+    states.insert(name, VisitState::Visited);
+
+    None
+}
+
+/// Selects one provider for every capability and rewrites the dependencies on capabilities to the selected providers.
+///
+/// An already installed provider is preferred, otherwise the first provider for the current platform is selected.
+/// The providers which are neither selected nor depended on by name are not installed, and are returned separately
+/// so that they are reported as skipped.
+pub fn resolve_capabilities(
+    tools_installation_detail: Vec<InstallationDetailItem>,
+    is_installed: impl Fn(&InstallationDetailItem) -> bool,
+) -> (Vec<InstallationDetailItem>, Vec<UnselectedProvider>) {
+    let names: HashSet<String> = tools_installation_detail.iter().map(|tool| tool.name.clone()).collect();
+
+    let mut selected_providers: HashMap<String, String> = HashMap::new();
+    for tool in &tools_installation_detail {
+        for capability in &tool.provides {
+            if selected_providers.contains_key(capability) {
+                continue;
+            }
+            let providers = tools_installation_detail
+                .iter()
+                .filter(|provider| provider.provides.contains(capability))
+                .collect::<Vec<_>>();
+            let provider = if providers.len() > 1 {
+                providers
+                    .iter()
+                    .find(|provider| is_installed(provider))
+                    .unwrap_or(&providers[0])
+            } else {
+                &providers[0]
+            };
+            selected_providers.insert(capability.clone(), provider.name.clone());
+        }
+    }
+
+    let depended_names: HashSet<&String> = tools_installation_detail
+        .iter()
+        .flat_map(|tool| tool.depends_on.iter())
+        .filter(|dependency| names.contains(*dependency))
+        .collect();
+    let is_wanted = |tool: &InstallationDetailItem| {
+        tool.provides.is_empty()
+            || depended_names.contains(&tool.name)
+            || tool
+                .provides
+                .iter()
+                .any(|capability| selected_providers.get(capability) == Some(&tool.name))
+    };
+    let wanted_tools = tools_installation_detail.iter().map(is_wanted).collect::<Vec<_>>();
+
+    let mut unselected_providers = vec![];
+    let tools = tools_installation_detail
+        .into_iter()
+        .zip(wanted_tools)
+        .filter_map(|(mut tool, is_wanted)| {
+            if !is_wanted {
+                // every capability of an unwanted tool has another selected provider
+                let capability = &tool.provides[0];
+                unselected_providers.push(UnselectedProvider {
+                    name: tool.name,
+                    capability: capability.clone(),
+                    selected: selected_providers[capability].clone(),
+                });
+                return None;
+            }
+            tool.depends_on = tool
+                .depends_on
+                .into_iter()
+                .map(|dependency| {
+                    if names.contains(&dependency) {
+                        dependency
+                    } else {
+                        selected_providers.get(&dependency).cloned().unwrap_or(dependency)
+                    }
+                })
+                .collect();
+            Some(tool)
+        })
+        .collect();
+    (tools, unselected_providers)
+}
+
+/// Groups the tools into layers where every tool only depends on tools of the previous layers,
+/// so the tools of one layer can be installed in parallel.
+///
+/// Dependencies without an installation for the current platform are ignored.
+pub fn resolve_installation_layers(
+    tools_installation_detail: Vec<InstallationDetailItem>,
+) -> Result<Vec<Vec<InstallationDetailItem>>> {
+    let names: HashSet<String> = tools_installation_detail.iter().map(|tool| tool.name.clone()).collect();
+
+    let mut layers: Vec<Vec<InstallationDetailItem>> = vec![];
+    let mut resolved: HashSet<String> = HashSet::new();
+    let mut pending = tools_installation_detail;
+
+    while !pending.is_empty() {
+        let (ready, blocked): (Vec<_>, Vec<_>) = pending.into_iter().partition(|tool| {
+            tool.depends_on
+                .iter()
+                .all(|dependency| resolved.contains(dependency) || !names.contains(dependency))
+        });
+        if ready.is_empty() {
+            let blocked_names = blocked.iter().map(|tool| tool.name.as_str()).collect::<Vec<_>>();
+            return Err(anyhow::anyhow!(
+                "Dependency cycle detected between: {}",
+                blocked_names.join(", ")
+            ));
+        }
+        resolved.extend(ready.iter().map(|tool| tool.name.clone()));
+        layers.push(ready);
+        pending = blocked;
+    }
+
+    Ok(layers)
+}
+
+#[cfg(test)]
+mod test_dependency {
+    use super::*;
+    use crate::{installation::filter_tool_installation_detail, ToolkitsManifest};
+
+    fn create_manifest(dependencies: &[(&str, &[&str])]) -> ToolkitsManifest {
+        create_manifest_with_capabilities(
+            &dependencies
+                .iter()
+                .map(|(name, depends_on)| (*name, *depends_on, &[] as &[&str]))
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    fn create_manifest_with_capabilities(tools: &[(&str, &[&str], &[&str])]) -> ToolkitsManifest {
+        let toolkits = tools
+            .iter()
+            .map(|(name, depends_on, provides)| {
+                serde_json::json!({
+                    "name": name,
+                    "description": "",
+                    "dependsOn": depends_on,
+                    "provides": provides,
+                    "installations": [
+                        { "os": std::env::consts::OS, "type": "shell", "id": name, "source": "true" }
+                    ]
+                })
+            })
+            .collect::<Vec<_>>();
+        serde_json::from_value(serde_json::json!({
+            "author": "",
+            "version": "",
+            "description": "",
+            "toolkits": toolkits
+        }))
+        .unwrap()
+    }
+
+    fn layer_names(layers: &[Vec<InstallationDetailItem>]) -> Vec<Vec<&str>> {
+        layers
+            .iter()
+            .map(|layer| layer.iter().map(|tool| tool.name.as_str()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_resolve_layers() -> Result<()> {
+        let manifest = create_manifest(&[
+            ("fnm", &["homebrew"]),
+            ("homebrew", &[]),
+            ("Google Chrome", &[]),
+            ("pnpm", &["fnm", "homebrew"]),
+        ]);
+        validate_dependencies(&manifest.toolkits)?;
+        let layers = resolve_installation_layers(filter_tool_installation_detail(&manifest.toolkits)?)?;
+        assert_eq!(
+            layer_names(&layers),
+            vec![vec!["homebrew", "Google Chrome"], vec!["fnm"], vec!["pnpm"]]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_detect_cycle() {
+        let manifest = create_manifest(&[("a", &["b"]), ("b", &["c"]), ("c", &["b"])]);
+        let err = validate_dependencies(&manifest.toolkits).unwrap_err();
+        assert_eq!(err.to_string(), "Dependency cycle detected: b -> c -> b");
+    }
+
+    #[test]
+    fn test_detect_unknown_dependency() {
+        let manifest = create_manifest(&[("fnm", &["brew"])]);
+        let err = validate_dependencies(&manifest.toolkits).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Tool 'fnm' depends on 'brew', which is neither a tool nor a capability provided by a tool in the manifest"
+        );
+    }
+
+    #[test]
+    fn test_resolve_capability_with_first_provider() -> Result<()> {
+        let manifest = create_manifest_with_capabilities(&[
+            ("fnm", &[], &["node"]),
+            ("nvm", &[], &["node"]),
+            ("pnpm", &["node"], &[]),
+        ]);
+        validate_dependencies(&manifest.toolkits)?;
+        let (tools, unselected_providers) =
+            resolve_capabilities(filter_tool_installation_detail(&manifest.toolkits)?, |_| false);
+        let layers = resolve_installation_layers(tools)?;
+        assert_eq!(layer_names(&layers), vec![vec!["fnm"], vec!["pnpm"]]);
+        assert_eq!(layers[1][0].depends_on, vec!["fnm"]);
+        assert_eq!(
+            unselected_providers,
+            vec![UnselectedProvider {
+                name: "nvm".to_string(),
+                capability: "node".to_string(),
+                selected: "fnm".to_string(),
+            }]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_capability_with_installed_provider() -> Result<()> {
+        let manifest = create_manifest_with_capabilities(&[
+            ("fnm", &[], &["node"]),
+            ("nvm", &[], &["node"]),
+            ("pnpm", &["node"], &[]),
+        ]);
+        let (tools, unselected_providers) =
+            resolve_capabilities(filter_tool_installation_detail(&manifest.toolkits)?, |tool| {
+                tool.name == "nvm"
+            });
+        let layers = resolve_installation_layers(tools)?;
+        assert_eq!(layer_names(&layers), vec![vec!["nvm"], vec!["pnpm"]]);
+        assert_eq!(unselected_providers[0].name, "fnm");
+        assert_eq!(unselected_providers[0].selected, "nvm");
+        Ok(())
+    }
+
+    #[test]
+    fn test_detect_cycle_through_capability() {
+        let manifest = create_manifest_with_capabilities(&[("fnm", &["pnpm"], &["node"]), ("pnpm", &["node"], &[])]);
+        let err = validate_dependencies(&manifest.toolkits).unwrap_err();
+        assert_eq!(err.to_string(), "Dependency cycle detected: fnm -> pnpm -> fnm");
+    }
+}
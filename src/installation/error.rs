@@ -0,0 +1,43 @@
+use std::{
+    error::Error,
+    fmt::{self, Display},
+};
+
+use crate::CommandTimeoutError;
+
+/// An installation failure which may not happen with another installation candidate of the same tool,
+/// e.g. a failed download or a failed installer.
+#[derive(Debug)]
+pub struct RetriableError(anyhow::Error);
+
+impl RetriableError {
+    pub fn wrap(err: anyhow::Error) -> anyhow::Error {
+        anyhow::Error::new(RetriableError(err))
+    }
+}
+
+impl Display for RetriableError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+impl Error for RetriableError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.0.source()
+    }
+}
+
+pub fn is_retriable(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| cause.is::<RetriableError>())
+}
+
+pub fn is_timed_out(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        cause.is::<CommandTimeoutError>()
+            // a retriable error is displayed as the error it wraps, which is not part of the chain
+            || cause
+                .downcast_ref::<RetriableError>()
+                .is_some_and(|retriable| is_timed_out(&retriable.0))
+    })
+}
@@ -0,0 +1,45 @@
+use indicatif::ProgressBar;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::{run_command_steps, CommandRunner, CommandSteps, HookResult, HookStatus, InstallContext, SPINNER_STYLE};
+
+/// Runs a manifest hook, e.g. `beforeAll`, with its own spinner and log. The commands of the hook are killed when it is
+/// interrupted by Ctrl-C.
+pub async fn run_hook(
+    name: &str,
+    steps: &CommandSteps,
+    timeout: Option<Duration>,
+    runner: Arc<dyn CommandRunner>,
+) -> HookResult {
+    let pb = ProgressBar::new(100);
+    pb.set_style(SPINNER_STYLE.clone());
+    pb.set_prefix("[hooks]");
+    pb.enable_steady_tick(Duration::from_millis(120));
+    let ctx = InstallContext::new(&format!("hooks.{}", name), pb)
+        .with_runner(runner)
+        .with_timeout(timeout);
+    let started_at = Instant::now();
+    let result = tokio::select! {
+        result = run_command_steps(name, steps, &ctx) => Some(result),
+        _ = tokio::signal::ctrl_c() => None,
+    };
+    ctx.pb().finish_and_clear();
+    let (status, errors) = match result {
+        Some(Ok(())) => (HookStatus::Succeeded, vec![]),
+        None => (HookStatus::Interrupted, vec!["Interrupted by the user".to_string()]),
+        Some(Err(err)) => {
+            ctx.log(&format!("Error: {:?}", err));
+            (HookStatus::Failed, err.chain().map(|cause| cause.to_string()).collect())
+        }
+    };
+    HookResult {
+        name: name.to_string(),
+        status,
+        duration: started_at.elapsed(),
+        errors,
+        log_path: ctx.log_path().map(|path| path.to_path_buf()),
+    }
+}
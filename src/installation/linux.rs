@@ -1,7 +1,379 @@
-pub async fn install() -> anyhow::Result<()> {
-    Ok(())
+#[cfg(target_os = "linux")]
+pub mod linux_installation {
+    use crate::{
+        installation::shell::install_tool_by_shell, CommandRunner, InstallContext, InstallStatus,
+        InstallationDetailItem, Type,
+    };
+    use anyhow::Result;
+
+    pub const SUPPORTED_TYPES: &[Type] = &[Type::Shell];
+
+    pub async fn is_tool_installed(
+        tool_installation_detail: &InstallationDetailItem,
+        runner: &dyn CommandRunner,
+    ) -> bool {
+        match tool_installation_detail.r#type {
+            Type::Shell => runner.exists(&tool_installation_detail.id).await.unwrap_or(false),
+            _ => false,
+        }
+    }
+
+    pub async fn detect_app_version(
+        _tool_installation_detail: &InstallationDetailItem,
+        _runner: &dyn CommandRunner,
+    ) -> Option<String> {
+        None
+    }
+
+    pub async fn install_tool(
+        tool_installation_detail: &InstallationDetailItem,
+        ctx: &InstallContext,
+    ) -> Result<InstallStatus> {
+        match tool_installation_detail.r#type {
+            Type::Shell => install_tool_by_shell(tool_installation_detail, ctx).await,
+            _ => Err(anyhow::anyhow!(
+                "Unsupported installation type: {}",
+                tool_installation_detail.r#type
+            )),
+        }
+    }
 }
 
 #[cfg(target_os = "linux")]
 #[cfg(test)]
-mod test_install_fn_on_linux {}
+mod test_install_fn_on_linux {
+    use crate::{
+        installation::{
+            filter_tool_installation_detail, install_tools, install_with_runner, resolve_installation_layers,
+            InstallToolsOptions,
+        },
+        ExitStatus, FakeCommandRunner, FakeOutput, InstallOpts, ManifestSourceOpts, ReportFormat,
+        ToolInstallationManifest, ToolInstallationStatus, LOCKFILE_NAME,
+    };
+    use std::{sync::Arc, time::Duration};
+
+    #[tokio::test]
+    async fn test_install_with_fake_runner() {
+        let tools: Vec<ToolInstallationManifest> = serde_json::from_value(serde_json::json!([
+            {
+                "name": "fnm",
+                "installations": [{
+                    "os": "linux",
+                    "type": "shell",
+                    "id": "fnm",
+                    "source": "curl -fsSL https://fnm.vercel.app/install | bash",
+                    "preInstall": "mkdir -p ~/.local/share/fnm",
+                    "postInstall": "fnm install 22"
+                }]
+            },
+            {
+                "name": "ripgrep",
+                "installations": [{ "os": "linux", "type": "shell", "id": "rg", "source": "cargo install ripgrep" }]
+            },
+            {
+                "name": "broken",
+                "installations": [{ "os": "linux", "type": "shell", "id": "broken", "source": "curl -fsSL https://example.com/broken.sh | bash" }]
+            }
+        ]))
+        .unwrap();
+        let runner = Arc::new(
+            FakeCommandRunner::default()
+                .with_existing_command("rg")
+                .with_output(
+                    "fnm.vercel.app",
+                    FakeOutput::success("Downloading fnm...").installing("fnm"),
+                )
+                .with_output("fnm --version", FakeOutput::success("fnm 1.37.1"))
+                .with_output("broken.sh", FakeOutput::failure(6, "curl: (6) Could not resolve host")),
+        );
+        let layers = resolve_installation_layers(filter_tool_installation_detail(&tools).unwrap()).unwrap();
+
+        let results = install_tools(
+            layers,
+            InstallToolsOptions {
+                runner: runner.clone(),
+                ..Default::default()
+            },
+        )
+        .await;
+
+        let statuses = results
+            .iter()
+            .map(|result| (result.name.as_str(), result.status))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            statuses,
+            vec![
+                ("fnm", ToolInstallationStatus::Installed),
+                ("ripgrep", ToolInstallationStatus::AlreadyInstalled),
+                ("broken", ToolInstallationStatus::Failed),
+            ]
+        );
+        let version = results[0].locked_tool.as_ref().and_then(|tool| tool.version.clone());
+        assert_eq!(version.as_deref(), Some("1.37.1"));
+        assert!(
+            results[2].errors[0].ends_with("[stderr] curl: (6) Could not resolve host"),
+            "{:?}",
+            results[2].errors
+        );
+
+        // the tools are installed in parallel, so only the commands of a tool are in order
+        let fnm_commands = runner
+            .commands()
+            .into_iter()
+            .filter(|command| command.contains("fnm"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            fnm_commands,
+            vec![
+                "mkdir -p ~/.local/share/fnm",
+                "curl -fsSL https://fnm.vercel.app/install | bash",
+                "command -v fnm",
+                "fnm install 22",
+                "fnm --version",
+            ]
+        );
+        assert!(!runner.commands().contains(&"cargo install ripgrep".to_string()));
+    }
+
+    async fn install_failing_fnm(bin_path: &std::path::Path) -> Vec<crate::ToolInstallationResult> {
+        let tools: Vec<ToolInstallationManifest> = serde_json::from_value(serde_json::json!([{
+            "name": "fnm",
+            "installations": [{
+                "os": "linux",
+                "type": "shell",
+                "id": "fnm",
+                "source": "curl -fsSL https://fnm.vercel.app/install | bash",
+                "postInstall": "fnm install 22"
+            }]
+        }]))
+        .unwrap();
+        let runner = Arc::new(
+            FakeCommandRunner::default()
+                .with_output("command -v fnm", FakeOutput::success(&bin_path.to_string_lossy()))
+                .with_output(
+                    "fnm install",
+                    FakeOutput::failure(1, "error: Can't download the requested binary"),
+                ),
+        );
+        let layers = resolve_installation_layers(filter_tool_installation_detail(&tools).unwrap()).unwrap();
+
+        install_tools(
+            layers,
+            InstallToolsOptions {
+                runner,
+                ..Default::default()
+            },
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_roll_back_shell_installation() {
+        let bin_path = std::env::temp_dir().join(format!("toolkit-test-rollback-{}", std::process::id()));
+        // written by the install script
+        std::fs::write(&bin_path, "#!/bin/sh\n").unwrap();
+
+        let results = install_failing_fnm(&bin_path).await;
+
+        assert_eq!(results[0].status, ToolInstallationStatus::Failed);
+        // the command installed before the failed post-install is removed
+        assert!(!bin_path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_keep_existing_command_on_rollback() {
+        let bin_path = std::env::temp_dir().join(format!("toolkit-test-rollback-existing-{}", std::process::id()));
+        // e.g. a binary of a package manager which the install script only added to the `PATH`
+        std::fs::write(&bin_path, "#!/bin/sh\n").unwrap();
+        let status = std::process::Command::new("touch")
+            .args(["-d", "2020-01-01"])
+            .arg(&bin_path)
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let results = install_failing_fnm(&bin_path).await;
+
+        assert_eq!(results[0].status, ToolInstallationStatus::Failed);
+        assert!(bin_path.exists());
+        std::fs::remove_file(&bin_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_install_interactive() {
+        let tools: Vec<ToolInstallationManifest> = serde_json::from_value(serde_json::json!([
+            {
+                "name": "rustup",
+                "installations": [{
+                    "os": "linux",
+                    "type": "shell",
+                    "id": "rustup",
+                    "source": "curl -sSf https://sh.rustup.rs | sh",
+                    "postInstall": "rustup toolchain install stable",
+                    "interactive": true
+                }]
+            },
+            {
+                "name": "fnm",
+                "installations": [{ "os": "linux", "type": "shell", "id": "fnm", "source": "curl -fsSL https://fnm.vercel.app/install | bash" }]
+            }
+        ]))
+        .unwrap();
+        let runner = Arc::new(
+            FakeCommandRunner::default()
+                .with_output("sh.rustup.rs", FakeOutput::success("").installing("rustup"))
+                .with_output("fnm.vercel.app", FakeOutput::success("").installing("fnm")),
+        );
+        let layers = resolve_installation_layers(filter_tool_installation_detail(&tools).unwrap()).unwrap();
+
+        let results = install_tools(
+            layers,
+            InstallToolsOptions {
+                runner: runner.clone(),
+                ..Default::default()
+            },
+        )
+        .await;
+
+        assert!(results
+            .iter()
+            .all(|result| result.status == ToolInstallationStatus::Installed));
+        assert_eq!(
+            runner.interactive_commands(),
+            vec!["curl -sSf https://sh.rustup.rs | sh", "rustup toolchain install stable"]
+        );
+        assert!(runner
+            .commands()
+            .contains(&"curl -fsSL https://fnm.vercel.app/install | bash".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_install_timed_out() {
+        let tools: Vec<ToolInstallationManifest> = serde_json::from_value(serde_json::json!([{
+            "name": "stalled",
+            "installations": [{ "os": "linux", "type": "shell", "id": "toolkit-test-stalled", "source": "sleep 30" }]
+        }]))
+        .unwrap();
+        let layers = resolve_installation_layers(filter_tool_installation_detail(&tools).unwrap()).unwrap();
+
+        let results = install_tools(
+            layers,
+            InstallToolsOptions {
+                timeout: Some(Duration::from_secs(1)),
+                ..Default::default()
+            },
+        )
+        .await;
+
+        assert_eq!(results[0].status, ToolInstallationStatus::TimedOut);
+        assert_eq!(results[0].errors, vec!["`sleep 30` timed out after 1s"]);
+    }
+
+    #[tokio::test]
+    async fn test_install_manifest_with_fake_runner() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest_path = dir.path().join("toolkits.manifest.json");
+        let manifest = serde_json::json!({
+            "description": "Node toolkits",
+            "version": "1.0.0",
+            "author": "toolkit",
+            "hooks": { "beforeAll": "echo onboarding" },
+            "toolkits": [
+                {
+                    "name": "fnm",
+                    "provides": ["node"],
+                    "installations": [{ "os": "linux", "type": "shell", "id": "fnm", "source": "curl -fsSL https://fnm.vercel.app/install | bash" }]
+                },
+                {
+                    "name": "nvm",
+                    "provides": ["node"],
+                    "installations": [{ "os": "linux", "type": "shell", "id": "nvm", "source": "curl -fsSL https://nvm.sh/install.sh | bash" }]
+                }
+            ]
+        });
+        std::fs::write(&manifest_path, manifest.to_string()).unwrap();
+        let opts = InstallOpts {
+            source: ManifestSourceOpts {
+                manifest: vec![manifest_path.to_string_lossy().to_string()],
+                offline: true,
+                insecure_skip_verify: false,
+            },
+            report: None,
+            report_format: ReportFormat::Json,
+            fail_fast: false,
+            jobs: None,
+            timeout: 0,
+            locked: false,
+            write_lock: false,
+            no_rollback: false,
+        };
+        let runner = Arc::new(FakeCommandRunner::default().with_existing_command("nvm"));
+
+        let report = install_with_runner(&opts, runner.clone()).await.unwrap();
+
+        // the provider which is already installed is picked with the injected runner, not the machine
+        let statuses = report
+            .tools
+            .iter()
+            .map(|result| (result.name.as_str(), result.status))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            statuses,
+            vec![
+                ("nvm", ToolInstallationStatus::AlreadyInstalled),
+                ("fnm", ToolInstallationStatus::NotSelected),
+            ]
+        );
+        assert_eq!(report.tools[1].errors, vec!["'node' is provided by nvm"]);
+        assert_eq!(runner.commands(), vec!["echo onboarding", "nvm --version"]);
+    }
+
+    #[tokio::test]
+    async fn test_install_with_invalid_lockfile() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest_path = dir.path().join("toolkits.manifest.json");
+        let manifest = serde_json::json!({
+            "description": "Node toolkits",
+            "version": "1.0.0",
+            "author": "toolkit",
+            "toolkits": [{
+                "name": "fnm",
+                "installations": [{ "os": "linux", "type": "shell", "id": "fnm", "source": "curl -fsSL https://fnm.vercel.app/install | bash" }]
+            }]
+        });
+        std::fs::write(&manifest_path, manifest.to_string()).unwrap();
+        let opts = InstallOpts {
+            source: ManifestSourceOpts {
+                manifest: vec![manifest_path.to_string_lossy().to_string()],
+                offline: true,
+                insecure_skip_verify: false,
+            },
+            report: None,
+            report_format: ReportFormat::Json,
+            fail_fast: false,
+            jobs: None,
+            timeout: 0,
+            locked: true,
+            write_lock: false,
+            no_rollback: false,
+        };
+        let runner = Arc::new(FakeCommandRunner::default());
+
+        // the lockfile is missing
+        let err = install_with_runner(&opts, runner.clone()).await.unwrap_err();
+        assert_eq!(ExitStatus::from_error(&err), ExitStatus::ManifestError);
+
+        // the lockfile cannot be parsed
+        std::fs::write(dir.path().join(LOCKFILE_NAME), "{").unwrap();
+        let err = install_with_runner(&opts, runner.clone()).await.unwrap_err();
+        assert_eq!(ExitStatus::from_error(&err), ExitStatus::ManifestError);
+
+        // the manifest changed since it was locked
+        let lockfile = serde_json::json!({ "version": 1, "platforms": {} });
+        std::fs::write(dir.path().join(LOCKFILE_NAME), lockfile.to_string()).unwrap();
+        let err = install_with_runner(&opts, runner.clone()).await.unwrap_err();
+        assert_eq!(ExitStatus::from_error(&err), ExitStatus::ManifestError);
+        assert!(runner.commands().is_empty());
+    }
+}
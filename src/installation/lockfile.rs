@@ -0,0 +1,376 @@
+use anyhow::Result;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    installation::manifest_source::ManifestSource, quote_shell_arg, CommandOptions, CommandRunner,
+    InstallationDetailItem, ToolInstallationResult, Type,
+};
+
+pub const LOCKFILE_NAME: &str = "toolkit.lock";
+
+const LOCKFILE_VERSION: u32 = 1;
+
+lazy_static! {
+    static ref VERSION_REGEX: Regex = Regex::new(r"\d+(\.\d+)+").unwrap();
+}
+
+/// The resolved installations of the tools, per platform, written by `install` and enforced by `install --locked`.
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Lockfile {
+    pub version: u32,
+    /// The locked tools by name, per `<os>-<arch>` platform.
+    #[serde(default)]
+    pub platforms: BTreeMap<String, BTreeMap<String, LockedTool>>,
+}
+
+impl Default for Lockfile {
+    fn default() -> Self {
+        Self {
+            version: LOCKFILE_VERSION,
+            platforms: BTreeMap::new(),
+        }
+    }
+}
+
+/// The installation of a tool as it was resolved when the lockfile was written.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct LockedTool {
+    pub r#type: Type,
+    /// The url of the download after redirects, or the url of the pinned script.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// The version detected after the installation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+}
+
+/// A file downloaded by an installation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Artifact {
+    pub url: String,
+    pub sha256: String,
+    pub size: u64,
+}
+
+impl LockedTool {
+    pub fn new(r#type: Type, artifact: Option<Artifact>, version: Option<String>) -> Self {
+        Self {
+            r#type,
+            url: artifact.as_ref().map(|artifact| artifact.url.clone()),
+            sha256: artifact.as_ref().map(|artifact| artifact.sha256.clone()),
+            size: artifact.as_ref().map(|artifact| artifact.size),
+            version,
+        }
+    }
+
+    pub fn verify_artifact(&self, artifact: &Artifact) -> Result<()> {
+        if let Some(sha256) = &self.sha256 {
+            if !sha256.eq_ignore_ascii_case(&artifact.sha256) {
+                return Err(anyhow::anyhow!(
+                    "The sha256 checksum of '{}' is {}, but {} is locked in {}",
+                    artifact.url,
+                    artifact.sha256,
+                    sha256,
+                    LOCKFILE_NAME
+                ));
+            }
+        }
+        if let Some(size) = self.size {
+            if size != artifact.size {
+                return Err(anyhow::anyhow!(
+                    "The size of '{}' is {} bytes, but {} bytes are locked in {}",
+                    artifact.url,
+                    artifact.size,
+                    size,
+                    LOCKFILE_NAME
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn verify_version(&self, version: Option<&str>) -> Result<()> {
+        match (self.version.as_deref(), version) {
+            (Some(locked_version), Some(version)) if locked_version != version => Err(anyhow::anyhow!(
+                "The installed version {} does not match the version {} locked in {}",
+                version,
+                locked_version,
+                LOCKFILE_NAME
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Lockfile {
+    /// The locked tools of the current platform.
+    pub fn tools(&self) -> BTreeMap<String, LockedTool> {
+        self.platforms.get(&get_platform_key()).cloned().unwrap_or_default()
+    }
+
+    /// Replaces the locked tools of the current platform with the successful installations, keeping the entries of
+    /// the tools which failed and dropping the tools which are no longer in the manifest.
+    pub fn update(&mut self, tool_names: &[String], results: &[ToolInstallationResult]) {
+        let tools = self.platforms.entry(get_platform_key()).or_default();
+        tools.retain(|name, _| tool_names.contains(name));
+        for result in results {
+            let Some(mut locked_tool) = result.locked_tool.clone() else {
+                continue;
+            };
+            // an already installed tool is not downloaded, so its previously locked download is kept
+            if let Some(previous) = tools.get(&result.name) {
+                if locked_tool.url.is_none() && previous.r#type == locked_tool.r#type {
+                    locked_tool.url = previous.url.clone();
+                    locked_tool.sha256 = previous.sha256.clone();
+                    locked_tool.size = previous.size;
+                }
+            }
+            tools.insert(result.name.clone(), locked_tool);
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        fs::write(path, content + "\n")
+            .map_err(|err| anyhow::anyhow!("Failed to write the lockfile '{}'. Error: {}", path.display(), err))
+    }
+}
+
+/// The directory of the first manifest when it is a local file. Its lockfile is written by every install.
+pub fn get_local_manifest_dir(manifests: &[String]) -> Option<PathBuf> {
+    manifests
+        .first()
+        .and_then(|manifest| match ManifestSource::parse(manifest) {
+            Ok(ManifestSource::File(path)) => path.parent().map(Path::to_path_buf),
+            _ => None,
+        })
+}
+
+/// The lockfile is next to the first manifest when it is a local file, or in the current directory.
+pub fn get_lockfile_path(manifests: &[String]) -> PathBuf {
+    get_local_manifest_dir(manifests)
+        .unwrap_or_default()
+        .join(LOCKFILE_NAME)
+}
+
+/// Loads the lockfile, or `None` when it does not exist.
+pub fn load_lockfile(path: &Path) -> Result<Option<Lockfile>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(path)?;
+    let lockfile: Lockfile = serde_json::from_str(&content)
+        .map_err(|err| anyhow::anyhow!("Invalid lockfile '{}'. Error: {}", path.display(), err))?;
+    if lockfile.version != LOCKFILE_VERSION {
+        return Err(anyhow::anyhow!(
+            "Unsupported version {} of the lockfile '{}'",
+            lockfile.version,
+            path.display()
+        ));
+    }
+    Ok(Some(lockfile))
+}
+
+/// Checks that every tool is locked with an installation which is still in the manifest.
+pub fn check_locked_tools(
+    items: &[InstallationDetailItem],
+    locked_tools: &BTreeMap<String, LockedTool>,
+    lockfile_path: &Path,
+) -> Result<()> {
+    let mut violations = vec![];
+    for item in items {
+        let Some(locked_tool) = locked_tools.get(&item.name) else {
+            violations.push(format!("{}: not locked", item.name));
+            continue;
+        };
+        let Some(candidate) = item
+            .candidates()
+            .find(|candidate| candidate.r#type == locked_tool.r#type)
+        else {
+            violations.push(format!(
+                "{}: locked as a {} installation, which is no longer in the manifest",
+                item.name, locked_tool.r#type
+            ));
+            continue;
+        };
+        let is_download = candidate.r#type != Type::Shell || candidate.script.is_some();
+        if is_download && locked_tool.url.is_none() {
+            violations.push(format!("{}: no download is locked", item.name));
+        }
+    }
+
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!(
+            "The manifest does not match the lockfile '{}'. Run `toolkit install` without --locked to update it:\n  {}",
+            lockfile_path.display(),
+            violations.join("\n  ")
+        ))
+    }
+}
+
+/// Detects the version of a command from the output of `<command> --version`.
+pub async fn detect_command_version(command: &str, runner: &dyn CommandRunner) -> Option<String> {
+    let program = format!("{} --version", quote_shell_arg(command));
+    let output = runner.run(&program, &CommandOptions::default()).await.ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    parse_version(&String::from_utf8_lossy(&output.stdout))
+}
+
+fn parse_version(output: &str) -> Option<String> {
+    VERSION_REGEX.find(output).map(|version| version.as_str().to_string())
+}
+
+fn get_platform_key() -> String {
+    format!("{}-{}", env::consts::OS, env::consts::ARCH)
+}
+
+#[cfg(test)]
+mod test_lockfile {
+    use super::*;
+    use crate::{installation::filter_tool_installation_detail, ToolInstallationManifest, ToolInstallationStatus};
+    use std::time::Duration;
+
+    fn create_items() -> Vec<InstallationDetailItem> {
+        let os = env::consts::OS;
+        let tools: Vec<ToolInstallationManifest> = serde_json::from_value(serde_json::json!([
+            {
+                "name": "Visual Studio Code",
+                "installations": [{ "os": os, "type": "zip", "id": "Visual Studio Code.app", "source": "https://update.code.visualstudio.com/latest/darwin/stable" }]
+            },
+            {
+                "name": "fnm",
+                "installations": [{ "os": os, "type": "shell", "id": "fnm", "source": "curl -fsSL https://fnm.vercel.app/install | bash" }]
+            }
+        ]))
+        .unwrap();
+        filter_tool_installation_detail(&tools).unwrap()
+    }
+
+    fn create_result(name: &str, locked_tool: Option<LockedTool>) -> ToolInstallationResult {
+        ToolInstallationResult {
+            name: name.to_string(),
+            status: ToolInstallationStatus::Installed,
+            duration: Duration::ZERO,
+            bytes_downloaded: 0,
+            errors: vec![],
+            log_path: None,
+            locked_tool,
+        }
+    }
+
+    fn create_artifact(sha256: &str) -> Artifact {
+        Artifact {
+            url: "https://vscode.download.prss.microsoft.com/VSCode-darwin-universal-1.89.0.zip".to_string(),
+            sha256: sha256.to_string(),
+            size: 42,
+        }
+    }
+
+    #[test]
+    fn test_update_lockfile() {
+        let tool_names = vec!["Visual Studio Code".to_string(), "fnm".to_string()];
+        let mut lockfile = Lockfile::default();
+        lockfile
+            .platforms
+            .entry(get_platform_key())
+            .or_default()
+            .insert("Removed Tool".to_string(), LockedTool::new(Type::Shell, None, None));
+        let locked_vscode = LockedTool::new(Type::Zip, Some(create_artifact("abc")), Some("1.89.0".to_string()));
+        lockfile.update(
+            &tool_names,
+            &[
+                create_result("Visual Studio Code", Some(locked_vscode.clone())),
+                create_result("fnm", None),
+            ],
+        );
+        assert_eq!(
+            lockfile.tools(),
+            BTreeMap::from([("Visual Studio Code".to_string(), locked_vscode.clone())])
+        );
+
+        // the download of an already installed tool is kept
+        let already_installed = LockedTool::new(Type::Zip, None, Some("1.89.0".to_string()));
+        lockfile.update(
+            &tool_names,
+            &[create_result("Visual Studio Code", Some(already_installed))],
+        );
+        assert_eq!(lockfile.tools()["Visual Studio Code"], locked_vscode);
+    }
+
+    #[test]
+    fn test_check_locked_tools() {
+        let items = create_items();
+        let lockfile_path = Path::new(LOCKFILE_NAME);
+        let mut locked_tools = BTreeMap::from([("fnm".to_string(), LockedTool::new(Type::Shell, None, None))]);
+        let err = check_locked_tools(&items, &locked_tools, lockfile_path)
+            .unwrap_err()
+            .to_string();
+        assert!(err.ends_with("\n  Visual Studio Code: not locked"), "{}", err);
+
+        locked_tools.insert(
+            "Visual Studio Code".to_string(),
+            LockedTool::new(Type::Dmg, Some(create_artifact("abc")), None),
+        );
+        let err = check_locked_tools(&items, &locked_tools, lockfile_path)
+            .unwrap_err()
+            .to_string();
+        assert!(err.ends_with("locked as a dmg installation, which is no longer in the manifest"));
+
+        locked_tools.insert(
+            "Visual Studio Code".to_string(),
+            LockedTool::new(Type::Zip, Some(create_artifact("abc")), None),
+        );
+        assert!(check_locked_tools(&items, &locked_tools, lockfile_path).is_ok());
+    }
+
+    #[test]
+    fn test_verify_locked_tool() {
+        let locked_tool = LockedTool::new(Type::Zip, Some(create_artifact("ABC")), Some("1.89.0".to_string()));
+        assert!(locked_tool.verify_artifact(&create_artifact("abc")).is_ok());
+        assert!(locked_tool.verify_artifact(&create_artifact("def")).is_err());
+        assert!(locked_tool.verify_version(Some("1.89.0")).is_ok());
+        assert!(locked_tool.verify_version(None).is_ok());
+        assert!(locked_tool.verify_version(Some("1.90.0")).is_err());
+    }
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(parse_version("fnm 1.37.0\n"), Some("1.37.0".to_string()));
+        assert_eq!(parse_version("v20.12.2"), Some("20.12.2".to_string()));
+        assert_eq!(parse_version("unknown"), None);
+    }
+
+    #[test]
+    fn test_get_lockfile_path() {
+        let manifests = vec![
+            "./config/toolkits.yaml".to_string(),
+            "https://example.com/team.yaml".to_string(),
+        ];
+        assert_eq!(get_local_manifest_dir(&manifests), Some(PathBuf::from("./config")));
+        assert_eq!(get_lockfile_path(&manifests), PathBuf::from("./config/toolkit.lock"));
+
+        // the lockfile of a remote or the default manifest is only written to the current directory on request
+        for manifest in ["https://example.com/team.yaml", crate::DEFAULT_MANIFEST, "-"] {
+            let manifests = vec![manifest.to_string()];
+            assert_eq!(get_local_manifest_dir(&manifests), None);
+            assert_eq!(get_lockfile_path(&manifests), PathBuf::from("toolkit.lock"));
+        }
+    }
+}
@@ -0,0 +1,320 @@
+use anyhow::Result;
+use reqwest::{
+    header::{CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+    StatusCode, Url,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::utils::{get_cache_dir, WARNING_EMOJI};
+
+/// The directory of the cached remote manifests, `$XDG_CACHE_HOME/toolkit/manifests` or `~/.cache/toolkit/manifests`.
+pub fn get_manifest_cache_dir() -> PathBuf {
+    get_cache_dir().join("manifests")
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CacheEntry {
+    url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_modified: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    content_type: Option<String>,
+    /// Seconds since the unix epoch when the content was last fetched or revalidated.
+    fetched_at: u64,
+    content: String,
+}
+
+/// A remote manifest with its Content-Type.
+pub struct RemoteManifest {
+    pub content: String,
+    pub content_type: Option<String>,
+}
+
+/// Fetches a remote manifest and caches it with its ETag and Last-Modified.
+///
+/// Later fetches send a conditional request and reuse the cached copy when the manifest is unchanged. When the fetch
+/// fails, or `offline` is set, the cached copy is used with a warning showing its age.
+pub async fn fetch_remote_manifest(url: &Url, offline: bool, cache_dir: &Path) -> Result<RemoteManifest> {
+    let cache_path = cache_dir.join(format!("{}.json", sanitize_url(url.as_str())));
+    let cached = read_cache_entry(&cache_path).filter(|entry| entry.url == url.as_str());
+
+    if offline {
+        let Some(entry) = cached else {
+            return Err(anyhow::anyhow!(
+                "No cached copy of '{}' is available in offline mode",
+                url
+            ));
+        };
+        eprintln!(
+            "{} Offline: using the cached copy of '{}' fetched {} ago",
+            WARNING_EMOJI,
+            url,
+            format_age(entry.fetched_at)
+        );
+        return Ok(entry.into());
+    }
+
+    match fetch(url, cached.as_ref()).await {
+        Ok(Some(mut entry)) => {
+            entry.fetched_at = now();
+            write_cache_entry(&cache_path, &entry);
+            Ok(entry.into())
+        }
+        Ok(None) => {
+            // not modified, so the cached copy is revalidated
+            let mut entry = cached.expect("a conditional request is only sent with a cached copy");
+            entry.fetched_at = now();
+            write_cache_entry(&cache_path, &entry);
+            Ok(entry.into())
+        }
+        Err(err) => match cached {
+            Some(entry) => {
+                eprintln!(
+                    "{} Failed to fetch '{}' ({}), using the cached copy fetched {} ago",
+                    WARNING_EMOJI,
+                    url,
+                    err,
+                    format_age(entry.fetched_at)
+                );
+                Ok(entry.into())
+            }
+            None => Err(err),
+        },
+    }
+}
+
+/// Returns `None` when the server answers that the cached copy is not modified.
+async fn fetch(url: &Url, cached: Option<&CacheEntry>) -> Result<Option<CacheEntry>> {
+    let mut request = reqwest::Client::new().get(url.clone());
+    let mut is_conditional = false;
+    if let Some(cached) = cached {
+        if let Some(etag) = &cached.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+            is_conditional = true;
+        }
+        if let Some(last_modified) = &cached.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+            is_conditional = true;
+        }
+    }
+    let response = request.send().await?.error_for_status()?;
+    if response.status() == StatusCode::NOT_MODIFIED {
+        // the body of a 304 is empty, so it is only valid as the answer to a conditional request
+        if !is_conditional {
+            return Err(anyhow::anyhow!(
+                "The server answered 304 Not Modified to a request without a cached copy"
+            ));
+        }
+        return Ok(None);
+    }
+
+    let header = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string())
+    };
+    let etag = header(ETAG);
+    let last_modified = header(LAST_MODIFIED);
+    let content_type = header(CONTENT_TYPE);
+    // the exact bytes are kept, as the signature is verified against them: decoding the text would drop a BOM
+    let content = String::from_utf8(response.bytes().await?.to_vec())
+        .map_err(|_| anyhow::anyhow!("The manifest '{}' is not valid UTF-8", url))?;
+    Ok(Some(CacheEntry {
+        url: url.to_string(),
+        etag,
+        last_modified,
+        content_type,
+        fetched_at: 0,
+        content,
+    }))
+}
+
+impl From<CacheEntry> for RemoteManifest {
+    fn from(entry: CacheEntry) -> Self {
+        RemoteManifest {
+            content: entry.content,
+            content_type: entry.content_type,
+        }
+    }
+}
+
+fn read_cache_entry(cache_path: &Path) -> Option<CacheEntry> {
+    let content = fs::read_to_string(cache_path).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+// the cache is an optimization, so failing to write it must not fail the installation
+fn write_cache_entry(cache_path: &Path, entry: &CacheEntry) {
+    let result = cache_path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(cache_path, serde_json::to_vec(entry)?));
+    if let Err(err) = result {
+        eprintln!(
+            "{} Failed to cache the manifest at '{}': {}",
+            WARNING_EMOJI,
+            cache_path.display(),
+            err
+        );
+    }
+}
+
+fn sanitize_url(url: &str) -> String {
+    url.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn format_age(fetched_at: u64) -> String {
+    let age = Duration::from_secs(now().saturating_sub(fetched_at)).as_secs();
+    let (value, unit) = match age {
+        0..=59 => (age, "second"),
+        60..=3599 => (age / 60, "minute"),
+        3600..=86399 => (age / 3600, "hour"),
+        _ => (age / 86400, "day"),
+    };
+    format!("{} {}{}", value, unit, if value == 1 { "" } else { "s" })
+}
+
+#[cfg(test)]
+mod test_manifest_cache {
+    use super::*;
+    use std::{
+        env,
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        sync::{Arc, Mutex},
+        thread,
+    };
+
+    /// Serves the manifest with an ETag and answers `304 Not Modified` to a matching `If-None-Match`. Returns the
+    /// url and the conditional request headers it received.
+    fn serve_manifest(responses: usize) -> (Url, Arc<Mutex<Vec<Option<String>>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}/toolkits.json", listener.local_addr().unwrap())).unwrap();
+        let requests = Arc::new(Mutex::new(vec![]));
+        let received = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().take(responses) {
+                let mut stream = stream.unwrap();
+                let mut if_none_match = None;
+                for line in BufReader::new(&stream).lines() {
+                    let line = line.unwrap();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(": ") {
+                        if name.eq_ignore_ascii_case("if-none-match") {
+                            if_none_match = Some(value.to_string());
+                        }
+                    }
+                }
+                let response = if if_none_match.as_deref() == Some("\"v1\"") {
+                    "HTTP/1.1 304 Not Modified\r\nConnection: close\r\n\r\n".to_string()
+                } else {
+                    let body = "\u{feff}{ \"author\": \"remote\", \"toolkits\": [] }";
+                    format!(
+                        "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    )
+                };
+                received.lock().unwrap().push(if_none_match);
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        (url, requests)
+    }
+
+    #[tokio::test]
+    async fn test_revalidate_and_fall_back_to_cache() -> Result<()> {
+        let cache_dir = env::temp_dir().join(format!("toolkit-test-cache-{}", now_nanos()));
+        let (url, requests) = serve_manifest(2);
+
+        let manifest = fetch_remote_manifest(&url, false, &cache_dir).await?;
+        // the BOM is kept, so that the signature is verified against the served bytes
+        assert!(manifest.content.starts_with("\u{feff}{"));
+        let manifest = fetch_remote_manifest(&url, false, &cache_dir).await?;
+        assert!(manifest.content.contains("remote"));
+        assert_eq!(*requests.lock().unwrap(), vec![None, Some("\"v1\"".to_string())]);
+
+        // the server is gone, so the cached copy is used
+        let manifest = fetch_remote_manifest(&url, false, &cache_dir).await?;
+        assert_eq!(manifest.content_type.as_deref(), Some("application/json"));
+        let manifest = fetch_remote_manifest(&url, true, &cache_dir).await?;
+        assert!(manifest.content.contains("remote"));
+
+        fs::remove_dir_all(&cache_dir)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_not_modified_without_cache() {
+        let cache_dir = env::temp_dir().join(format!("toolkit-test-cache-{}", now_nanos()));
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}/toolkits.json", listener.local_addr().unwrap())).unwrap();
+        thread::spawn(move || {
+            let mut stream = listener.incoming().next().unwrap().unwrap();
+            for line in BufReader::new(&stream).lines() {
+                if line.unwrap().is_empty() {
+                    break;
+                }
+            }
+            stream
+                .write_all(b"HTTP/1.1 304 Not Modified\r\nConnection: close\r\n\r\n")
+                .unwrap();
+        });
+
+        let err = fetch_remote_manifest(&url, false, &cache_dir).await.err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "The server answered 304 Not Modified to a request without a cached copy"
+        );
+        assert!(!cache_dir.exists());
+    }
+
+    #[tokio::test]
+    async fn test_offline_without_cache() {
+        let cache_dir = env::temp_dir().join(format!("toolkit-test-cache-{}", now_nanos()));
+        let url = Url::parse("https://example.com/toolkits.json").unwrap();
+        let err = fetch_remote_manifest(&url, true, &cache_dir).await.err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "No cached copy of 'https://example.com/toolkits.json' is available in offline mode"
+        );
+    }
+
+    #[test]
+    fn test_format_age() {
+        assert_eq!(format_age(now() - 1), "1 second");
+        assert_eq!(format_age(now() - 7200), "2 hours");
+        assert_eq!(format_age(now() - 3 * 86400), "3 days");
+    }
+
+    fn now_nanos() -> u128 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos()
+    }
+}
@@ -0,0 +1,102 @@
+use anyhow::Result;
+use clap::{parser::ValueSource, ArgMatches};
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use crate::{Cli, Commands};
+
+/// The file names of a project manifest, in the order of preference.
+pub const PROJECT_MANIFEST_NAMES: &[&str] = &[
+    "toolkit.json",
+    "toolkit.yaml",
+    "toolkit.yml",
+    "toolkit.toml",
+    ".toolkit.json",
+    ".toolkit.yaml",
+    ".toolkit.yml",
+    ".toolkit.toml",
+];
+
+/// Finds the manifest of the project in the directory or its parents, like `.nvmrc`. The search stops at the root of
+/// the git repository or of the filesystem.
+pub fn discover_project_manifest(start_dir: &Path) -> Option<PathBuf> {
+    for dir in start_dir.ancestors() {
+        let manifest_path = PROJECT_MANIFEST_NAMES
+            .iter()
+            .map(|name| dir.join(name))
+            .find(|path| path.is_file());
+        if manifest_path.is_some() {
+            return manifest_path;
+        }
+        if dir.join(".git").exists() {
+            return None;
+        }
+    }
+    None
+}
+
+/// Uses the project manifest instead of the default `--manifest` of the command. A manifest passed on the command
+/// line or in `TOOLKIT_MANIFEST` takes precedence over the project manifest.
+pub fn apply_project_manifest(cli: &mut Cli, matches: &ArgMatches) -> Result<()> {
+    let Some(manifest) = cli.command.as_mut().and_then(Commands::manifest_mut) else {
+        return Ok(());
+    };
+    if get_manifest_value_source(matches) != Some(ValueSource::DefaultValue) {
+        return Ok(());
+    }
+    if let Some(manifest_path) = discover_project_manifest(&env::current_dir()?) {
+        eprintln!("Using the project manifest {}", manifest_path.display());
+        *manifest = vec![manifest_path.to_string_lossy().to_string()];
+    }
+    Ok(())
+}
+
+fn get_manifest_value_source(matches: &ArgMatches) -> Option<ValueSource> {
+    match matches.subcommand() {
+        Some((_, matches)) => get_manifest_value_source(matches),
+        None => match matches.try_contains_id("manifest") {
+            Ok(true) => matches.value_source("manifest"),
+            _ => None,
+        },
+    }
+}
+
+#[cfg(test)]
+mod test_manifest_discovery {
+    use super::*;
+    use std::{
+        env, fs,
+        time::{SystemTime, UNIX_EPOCH},
+    };
+
+    #[test]
+    fn test_discover_project_manifest() -> anyhow::Result<()> {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos();
+        let root = env::temp_dir().join(format!("toolkit-test-discovery-{}", nanos));
+        let repository = root.join("repository");
+        let nested_dir = repository.join("packages").join("web");
+        fs::create_dir_all(&nested_dir)?;
+        fs::create_dir_all(repository.join(".git"))?;
+        fs::write(root.join("toolkit.json"), "{}")?;
+
+        // the manifest outside of the git repository is not used
+        assert_eq!(discover_project_manifest(&nested_dir), None);
+
+        fs::write(repository.join(".toolkit.yaml"), "toolkits: []")?;
+        assert_eq!(
+            discover_project_manifest(&nested_dir),
+            Some(repository.join(".toolkit.yaml"))
+        );
+
+        fs::write(nested_dir.join("toolkit.json"), "{}")?;
+        assert_eq!(
+            discover_project_manifest(&nested_dir),
+            Some(nested_dir.join("toolkit.json"))
+        );
+
+        fs::remove_dir_all(&root)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,269 @@
+use anyhow::Result;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::de::DeserializeOwned;
+use std::path::Path;
+
+lazy_static! {
+    static ref POSITION_REGEX: Regex = Regex::new(r" at line \d+ column \d+$").unwrap();
+    static ref PATH_PREFIX_REGEX: Regex = Regex::new(r"^[A-Za-z_$][\w$]*(\[\d+\]|\.[A-Za-z_$][\w$]*)*: ").unwrap();
+    static ref UNKNOWN_NAME_REGEX: Regex = Regex::new(r"unknown (field|variant) `([^`]*)`, expected (.*)$").unwrap();
+    static ref EXPECTED_NAME_REGEX: Regex = Regex::new(r"`([^`]*)`").unwrap();
+}
+
+/// Common spellings of the `os` and `arch` values which are not close enough to be found by the edit distance.
+const ALIASES: &[(&str, &str)] = &[
+    ("darwin", "macos"),
+    ("mac", "macos"),
+    ("osx", "macos"),
+    ("win", "windows"),
+    ("win32", "windows"),
+    ("arm64", "aarch64"),
+    ("amd64", "x86_64"),
+    ("x64", "x86_64"),
+];
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ManifestFormat {
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl ManifestFormat {
+    /// Detects the format by the file extension of a path or url. Defaults to JSON.
+    pub fn from_path(path: &str) -> Self {
+        let path = path.split(['?', '#']).next().unwrap_or(path);
+        let extension = Path::new(path)
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("yaml") | Some("yml") => ManifestFormat::Yaml,
+            Some("toml") => ManifestFormat::Toml,
+            _ => ManifestFormat::Json,
+        }
+    }
+
+    /// Detects the format by the content of a manifest without a path, e.g. read from stdin: JSON starts with `{`,
+    /// TOML parses as a TOML document, and anything else is read as YAML.
+    pub fn from_content(content: &str) -> Self {
+        let content = content.trim_start_matches('\u{feff}').trim_start();
+        if content.starts_with('{') {
+            ManifestFormat::Json
+        } else if toml::from_str::<toml::Table>(content).is_ok() {
+            ManifestFormat::Toml
+        } else {
+            ManifestFormat::Yaml
+        }
+    }
+
+    /// Detects the format by the Content-Type of a remote manifest, falling back to the extension of the url
+    /// for generic types like `text/plain` which are served by raw file hosts.
+    pub fn from_content_type(content_type: Option<&str>, url: &str) -> Self {
+        let content_type = content_type.unwrap_or_default().to_lowercase();
+        if content_type.contains("yaml") {
+            ManifestFormat::Yaml
+        } else if content_type.contains("toml") {
+            ManifestFormat::Toml
+        } else if content_type.contains("json") {
+            ManifestFormat::Json
+        } else {
+            ManifestFormat::from_path(url)
+        }
+    }
+
+    /// Parses the manifest, rejecting unknown fields. The error names the path of the invalid value, e.g.
+    /// `toolkits[2].installations[0].type`, its line and column, and the closest valid name when there is one.
+    pub fn parse<T: DeserializeOwned>(&self, content: &str) -> Result<T> {
+        let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+        match self {
+            ManifestFormat::Json => {
+                let deserializer = &mut serde_json::Deserializer::from_str(content);
+                let value = serde_path_to_error::deserialize(&mut *deserializer).map_err(|err| {
+                    let inner = err.inner();
+                    let position = (inner.line() > 0).then(|| (inner.line(), inner.column()));
+                    create_parse_error(&err.path().to_string(), &inner.to_string(), position)
+                })?;
+                deserializer
+                    .end()
+                    .map_err(|err| create_parse_error(".", &err.to_string(), Some((err.line(), err.column()))))?;
+                Ok(value)
+            }
+            ManifestFormat::Yaml => serde_path_to_error::deserialize(serde_norway::Deserializer::from_str(content))
+                .map_err(|err| {
+                    let position = err
+                        .inner()
+                        .location()
+                        .map(|location| (location.line(), location.column()));
+                    let message = err.inner().to_string();
+                    let message = PATH_PREFIX_REGEX.replace(&message, "");
+                    create_parse_error(&err.path().to_string(), &message, position)
+                }),
+            ManifestFormat::Toml => serde_path_to_error::deserialize(toml::Deserializer::new(content)).map_err(|err| {
+                let position = err.inner().span().map(|span| get_line_column(content, span.start));
+                create_parse_error(&err.path().to_string(), err.inner().message(), position)
+            }),
+        }
+    }
+}
+
+fn create_parse_error(path: &str, message: &str, position: Option<(usize, usize)>) -> anyhow::Error {
+    let message = POSITION_REGEX.replace(message.trim(), "");
+    let mut error = if path == "." {
+        message.to_string()
+    } else {
+        format!("{}: {}", path, message)
+    };
+    if let Some((line, column)) = position {
+        error.push_str(&format!(" at line {}, column {}", line, column));
+    }
+    if let Some(captures) = UNKNOWN_NAME_REGEX.captures(&message) {
+        let expected = EXPECTED_NAME_REGEX
+            .captures_iter(&captures[3])
+            .map(|expected| expected.get(1).unwrap().as_str())
+            .collect::<Vec<_>>();
+        if let Some(suggestion) = suggest_name(&captures[2], &expected) {
+            error.push_str(&format!(". Did you mean `{}`?", suggestion));
+        }
+    }
+    anyhow::anyhow!(error)
+}
+
+/// Returns the 1-based line and column of a byte offset.
+fn get_line_column(content: &str, offset: usize) -> (usize, usize) {
+    let before = &content[..offset.min(content.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map_or(0, |index| index + 1) + 1;
+    (line, column)
+}
+
+/// Returns the expected name closest to the unknown one, ignoring case, `-` and `_`.
+fn suggest_name<'a>(unknown: &str, expected: &[&'a str]) -> Option<&'a str> {
+    let normalize = |name: &str| name.to_lowercase().replace(['-', '_'], "");
+    let unknown = normalize(unknown);
+    if let Some((_, alias)) = ALIASES.iter().find(|(alias, _)| normalize(alias) == unknown) {
+        if let Some(name) = expected.iter().find(|name| *name == alias) {
+            return Some(name);
+        }
+    }
+    expected
+        .iter()
+        .map(|name| (strsim::levenshtein(&unknown, &normalize(name)), *name))
+        .filter(|(distance, _)| *distance <= (unknown.len() / 3).max(1))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, name)| name)
+}
+
+#[cfg(test)]
+mod test_manifest_format {
+    use super::*;
+    use crate::{get_tookits_manifest, ToolkitsManifest};
+
+    #[test]
+    fn test_detect_format() {
+        assert_eq!(ManifestFormat::from_path("toolkits.manifest.yml"), ManifestFormat::Yaml);
+        assert_eq!(ManifestFormat::from_path("./toolkits.TOML"), ManifestFormat::Toml);
+        assert_eq!(ManifestFormat::from_path("toolkits"), ManifestFormat::Json);
+        assert_eq!(
+            ManifestFormat::from_content_type(Some("application/yaml"), "https://example.com/manifest"),
+            ManifestFormat::Yaml
+        );
+        assert_eq!(
+            ManifestFormat::from_content_type(
+                Some("text/plain; charset=utf-8"),
+                "https://example.com/toolkits.toml?token=1"
+            ),
+            ManifestFormat::Toml
+        );
+        assert_eq!(
+            ManifestFormat::from_content("\n  { \"toolkits\": [] }"),
+            ManifestFormat::Json
+        );
+        assert_eq!(
+            ManifestFormat::from_content("[[toolkits]]\nname = \"web\"\n"),
+            ManifestFormat::Toml
+        );
+        assert_eq!(
+            ManifestFormat::from_content("# tools\ntoolkits:\n  - name: web\n"),
+            ManifestFormat::Yaml
+        );
+    }
+
+    #[tokio::test]
+    async fn test_formats_are_equivalent() -> Result<()> {
+        let json = serde_json::to_value(get_tookits_manifest("./fixtures/toolkits.manifest.json").await?)?;
+        let yaml = serde_json::to_value(get_tookits_manifest("./fixtures/toolkits.manifest.yaml").await?)?;
+        let toml = serde_json::to_value(get_tookits_manifest("./fixtures/toolkits.manifest.toml").await?)?;
+        assert_eq!(json, yaml);
+        assert_eq!(json, toml);
+        Ok(())
+    }
+
+    #[test]
+    fn test_report_invalid_value_path() {
+        let content = r#"{
+  "toolkits": [
+    {
+      "name": "fnm",
+      "installations": [{ "os": "macOS", "type": "shell", "id": "fnm", "source": "" }]
+    }
+  ]
+}"#;
+        let err = ManifestFormat::Json.parse::<ToolkitsManifest>(content).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "toolkits[0].installations[0].os: unknown variant `macOS`, expected one of `macos`, `linux`, `windows` at line 5, column 39. Did you mean `macos`?"
+        );
+
+        let content = "toolkits:\n  - name: fnm\n    installations:\n      - os: linux\n        arch: arm64\n";
+        let err = ManifestFormat::Yaml.parse::<ToolkitsManifest>(content).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("toolkits[0].installations[0].arch: unknown variant `arm64`"));
+        assert!(err
+            .to_string()
+            .ends_with("at line 5, column 15. Did you mean `aarch64`?"));
+    }
+
+    #[test]
+    fn test_parse_with_bom() {
+        let content = "\u{feff}{ \"author\": \"toolkit\", \"toolkits\": [] }";
+        let manifest = ManifestFormat::Json.parse::<ToolkitsManifest>(content).unwrap();
+        assert_eq!(manifest.author, "toolkit");
+    }
+
+    #[test]
+    fn test_reject_unknown_fields() {
+        let content = "[[toolkits]]\nname = \"fnm\"\ndepends_on = [\"node\"]\n";
+        let err = ManifestFormat::Toml.parse::<ToolkitsManifest>(content).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("toolkits[0].depends_on: unknown field `depends_on`"));
+        assert!(err
+            .to_string()
+            .ends_with("at line 3, column 1. Did you mean `dependsOn`?"));
+
+        let content = r#"{ "$schema": "./toolkits.schema.json", "toolkitz": [] }"#;
+        let err = ManifestFormat::Json.parse::<ToolkitsManifest>(content).unwrap_err();
+        assert!(err.to_string().ends_with("Did you mean `toolkits`?"));
+    }
+
+    #[test]
+    fn test_report_invalid_step_path() {
+        let content = r#"{ "toolkits": [{ "name": "fnm", "installations": [{
+            "os": "macos", "type": "shell", "id": "fnm", "source": "brew install fnm",
+            "postInstall": [{ "run": "fnm install 22" }, { "run": "corepack enable", "continueOnErorr": true }]
+        }] }] }"#;
+        let err = ManifestFormat::Json.parse::<ToolkitsManifest>(content).unwrap_err();
+        assert!(err.to_string().starts_with(
+            "toolkits[0].installations[0].postInstall[1].continueOnErorr: unknown field `continueOnErorr`"
+        ));
+        assert!(err.to_string().ends_with("Did you mean `continueOnError`?"), "{}", err);
+
+        let content = "toolkits:\n  - name: fnm\n    installations:\n      - { os: macos, type: shell, id: fnm, source: x, postInstall: 3 }\n";
+        let err = ManifestFormat::Yaml.parse::<ToolkitsManifest>(content).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("toolkits[0].installations[0].postInstall: invalid type: integer `3`, expected a command or a list of steps"));
+    }
+}
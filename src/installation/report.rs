@@ -0,0 +1,611 @@
+use crate::{LockedTool, ERROR_EMOJI, SUCCESS_EMOJI, WARNING_EMOJI};
+use anyhow::Result;
+use clap::ValueEnum;
+use console::{strip_ansi_codes, style};
+use serde::Serialize;
+use std::{
+    env,
+    fmt::{self, Display},
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+#[derive(Debug, Serialize, PartialEq, Copy, Clone)]
+#[serde(rename_all = "camelCase")]
+pub enum ToolInstallationStatus {
+    Installed,
+    AlreadyInstalled,
+    Failed,
+    /// Failed because a command of the installation did not finish within its timeout.
+    TimedOut,
+    Unsupported,
+    /// Not started because a tool it depends on was not installed.
+    Skipped,
+    /// Not finished because `--fail-fast` aborted the installation after another tool failed.
+    Cancelled,
+    /// Not finished because the installation was interrupted by Ctrl-C.
+    Interrupted,
+    /// Skipped because another provider of one of its capabilities was selected.
+    NotSelected,
+}
+
+impl ToolInstallationStatus {
+    pub fn is_success(&self) -> bool {
+        matches!(
+            self,
+            ToolInstallationStatus::Installed | ToolInstallationStatus::AlreadyInstalled
+        )
+    }
+
+    /// Whether the tool counts as a failure of the installation. A provider which was not selected does not.
+    pub fn is_failure(&self) -> bool {
+        !self.is_success() && *self != ToolInstallationStatus::NotSelected
+    }
+}
+
+impl Display for ToolInstallationStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ToolInstallationStatus::Installed => write!(f, "installed"),
+            ToolInstallationStatus::AlreadyInstalled => write!(f, "already installed"),
+            ToolInstallationStatus::Failed => write!(f, "failed"),
+            ToolInstallationStatus::TimedOut => write!(f, "timed out"),
+            ToolInstallationStatus::Unsupported => write!(f, "unsupported"),
+            ToolInstallationStatus::Skipped => write!(f, "skipped"),
+            ToolInstallationStatus::Cancelled => write!(f, "cancelled"),
+            ToolInstallationStatus::Interrupted => write!(f, "interrupted"),
+            ToolInstallationStatus::NotSelected => write!(f, "not selected"),
+        }
+    }
+}
+
+/// The typed result of installing a single tool.
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolInstallationResult {
+    pub name: String,
+    pub status: ToolInstallationStatus,
+    #[serde(rename = "durationMs", serialize_with = "serialize_duration_ms")]
+    pub duration: Duration,
+    pub bytes_downloaded: u64,
+    /// The error followed by its causes, outermost first. Empty unless the installation failed.
+    pub errors: Vec<String>,
+    pub log_path: Option<PathBuf>,
+    /// The resolved installation written to the lockfile. Only set when the installation succeeded.
+    #[serde(skip)]
+    pub locked_tool: Option<LockedTool>,
+}
+
+impl Display for ToolInstallationResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = style(&self.name).bold();
+        match self.status {
+            ToolInstallationStatus::Installed => write!(f, "{} {}: Installed Successfully", SUCCESS_EMOJI, name),
+            ToolInstallationStatus::AlreadyInstalled => write!(f, "{} {}: Already installed", SUCCESS_EMOJI, name),
+            ToolInstallationStatus::Skipped => {
+                write!(f, "{} {}: Skipped. Reason: {}", ERROR_EMOJI, name, self.reason())
+            }
+            ToolInstallationStatus::NotSelected => {
+                write!(f, "{} {}: Skipped. Reason: {}", WARNING_EMOJI, name, self.reason())
+            }
+            ToolInstallationStatus::Cancelled => {
+                write!(f, "{} {}: Cancelled. Reason: {}", ERROR_EMOJI, name, self.reason())
+            }
+            ToolInstallationStatus::Interrupted => write!(f, "{} {}: Interrupted", ERROR_EMOJI, name),
+            ToolInstallationStatus::TimedOut => {
+                write!(f, "{} {}: Timed out. Reason: {}", ERROR_EMOJI, name, self.reason())
+            }
+            ToolInstallationStatus::Failed | ToolInstallationStatus::Unsupported => {
+                write!(
+                    f,
+                    "{} {}: Failed to install. Reason: {}",
+                    ERROR_EMOJI,
+                    name,
+                    self.reason()
+                )
+            }
+        }
+    }
+}
+
+impl ToolInstallationResult {
+    /// Creates the result of a tool whose installation did not run to completion.
+    pub fn unfinished(name: &str, status: ToolInstallationStatus, reason: &str, duration: Duration) -> Self {
+        Self {
+            name: name.to_string(),
+            status,
+            duration,
+            bytes_downloaded: 0,
+            errors: vec![reason.to_string()],
+            log_path: None,
+            locked_tool: None,
+        }
+    }
+
+    fn reason(&self) -> String {
+        self.errors.first().cloned().unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Serialize, PartialEq, Copy, Clone)]
+#[serde(rename_all = "camelCase")]
+pub enum HookStatus {
+    Succeeded,
+    Failed,
+    /// Not run because the `beforeAll` hook failed or the installation was interrupted.
+    Skipped,
+    /// Not finished because the installation was interrupted by Ctrl-C.
+    Interrupted,
+}
+
+impl Display for HookStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HookStatus::Succeeded => write!(f, "succeeded"),
+            HookStatus::Failed => write!(f, "failed"),
+            HookStatus::Skipped => write!(f, "skipped"),
+            HookStatus::Interrupted => write!(f, "interrupted"),
+        }
+    }
+}
+
+/// The result of a manifest hook, e.g. `beforeAll`.
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HookResult {
+    pub name: String,
+    pub status: HookStatus,
+    #[serde(rename = "durationMs", serialize_with = "serialize_duration_ms")]
+    pub duration: Duration,
+    /// The error followed by its causes, outermost first. Empty unless the hook failed or was skipped.
+    pub errors: Vec<String>,
+    pub log_path: Option<PathBuf>,
+}
+
+impl Display for HookResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = style(format!("hooks.{}", self.name)).bold();
+        match self.status {
+            HookStatus::Succeeded => write!(f, "{} {}: Succeeded", SUCCESS_EMOJI, name),
+            HookStatus::Failed => write!(f, "{} {}: Failed. Reason: {}", ERROR_EMOJI, name, self.reason()),
+            HookStatus::Skipped => write!(f, "{} {}: Skipped. Reason: {}", ERROR_EMOJI, name, self.reason()),
+            HookStatus::Interrupted => write!(f, "{} {}: Interrupted", ERROR_EMOJI, name),
+        }
+    }
+}
+
+impl HookResult {
+    pub fn skipped(name: &str, reason: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            status: HookStatus::Skipped,
+            duration: Duration::ZERO,
+            errors: vec![reason.to_string()],
+            log_path: None,
+        }
+    }
+
+    fn reason(&self) -> String {
+        self.errors.first().cloned().unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InstallationReport {
+    pub manifest: String,
+    pub os: String,
+    pub arch: String,
+    #[serde(rename = "durationMs", serialize_with = "serialize_duration_ms")]
+    pub duration: Duration,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub hooks: Vec<HookResult>,
+    pub tools: Vec<ToolInstallationResult>,
+}
+
+impl InstallationReport {
+    pub fn new(manifest: &str, duration: Duration, tools: Vec<ToolInstallationResult>) -> Self {
+        Self {
+            manifest: manifest.to_string(),
+            os: env::consts::OS.to_string(),
+            arch: env::consts::ARCH.to_string(),
+            duration,
+            hooks: vec![],
+            tools,
+        }
+    }
+
+    pub fn with_hooks(mut self, hooks: Vec<HookResult>) -> Self {
+        self.hooks = hooks;
+        self
+    }
+
+    pub fn failures(&self) -> usize {
+        self.tools.iter().filter(|tool| tool.status.is_failure()).count()
+    }
+
+    pub fn hook_failures(&self) -> usize {
+        self.hooks
+            .iter()
+            .filter(|hook| hook.status == HookStatus::Failed)
+            .count()
+    }
+
+    pub fn is_interrupted(&self) -> bool {
+        self.tools
+            .iter()
+            .any(|tool| tool.status == ToolInstallationStatus::Interrupted)
+            || self.hooks.iter().any(|hook| hook.status == HookStatus::Interrupted)
+    }
+
+    pub fn render(&self, format: ReportFormat) -> Result<String> {
+        match format {
+            ReportFormat::Json => Ok(serde_json::to_string_pretty(self)?),
+            ReportFormat::Junit => Ok(self.to_junit()),
+            ReportFormat::Markdown => Ok(self.to_markdown()),
+        }
+    }
+
+    pub fn write(&self, path: &Path, format: ReportFormat) -> Result<()> {
+        let content = self.render(format)?;
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, content)
+            .map_err(|err| anyhow::anyhow!("Failed to write report to '{}'. Error: {}", path.display(), err))
+    }
+
+    fn to_junit(&self) -> String {
+        let skipped = self.tools.iter().filter(|tool| is_junit_skipped(tool.status)).count();
+        let hooks_skipped = self
+            .hooks
+            .iter()
+            .filter(|hook| matches!(hook.status, HookStatus::Skipped | HookStatus::Interrupted))
+            .count();
+        let failures = self
+            .tools
+            .iter()
+            .filter(|tool| tool.status.is_failure() && !is_junit_skipped(tool.status))
+            .count()
+            + self.hook_failures();
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        xml.push_str(&format!(
+            "<testsuite name=\"toolkit\" tests=\"{}\" failures=\"{}\" skipped=\"{}\" time=\"{:.3}\">\n",
+            self.tools.len() + self.hooks.len(),
+            failures,
+            skipped + hooks_skipped,
+            self.duration.as_secs_f64()
+        ));
+        for hook in &self.hooks {
+            xml.push_str(&format!(
+                "  <testcase classname=\"toolkit.hooks\" name=\"{}\" time=\"{:.3}\"",
+                escape_xml(&hook.name),
+                hook.duration.as_secs_f64()
+            ));
+            match hook.status {
+                HookStatus::Succeeded => {
+                    xml.push_str(" />\n");
+                    continue;
+                }
+                HookStatus::Skipped | HookStatus::Interrupted => {
+                    xml.push_str(&format!(
+                        ">\n    <skipped message=\"{}\" />\n",
+                        escape_xml(&hook.reason())
+                    ));
+                }
+                HookStatus::Failed => {
+                    xml.push_str(&format!(
+                        ">\n    <failure message=\"{}\">{}</failure>\n",
+                        escape_xml(&hook.reason()),
+                        escape_xml(&hook.errors.join("\n"))
+                    ));
+                }
+            }
+            if let Some(log_path) = &hook.log_path {
+                xml.push_str(&format!(
+                    "    <system-out>Log: {}</system-out>\n",
+                    escape_xml(&log_path.to_string_lossy())
+                ));
+            }
+            xml.push_str("  </testcase>\n");
+        }
+        for tool in &self.tools {
+            xml.push_str(&format!(
+                "  <testcase classname=\"toolkit.{}\" name=\"{}\" time=\"{:.3}\"",
+                escape_xml(&self.os),
+                escape_xml(&tool.name),
+                tool.duration.as_secs_f64()
+            ));
+            match tool.status {
+                ToolInstallationStatus::Installed | ToolInstallationStatus::AlreadyInstalled => {
+                    xml.push_str(" />\n");
+                    continue;
+                }
+                ToolInstallationStatus::Unsupported
+                | ToolInstallationStatus::Skipped
+                | ToolInstallationStatus::Cancelled
+                | ToolInstallationStatus::Interrupted
+                | ToolInstallationStatus::NotSelected => {
+                    xml.push_str(&format!(
+                        ">\n    <skipped message=\"{}\" />\n",
+                        escape_xml(&tool.reason())
+                    ));
+                }
+                ToolInstallationStatus::Failed | ToolInstallationStatus::TimedOut => {
+                    xml.push_str(&format!(
+                        ">\n    <failure message=\"{}\">{}</failure>\n",
+                        escape_xml(&tool.reason()),
+                        escape_xml(&tool.errors.join("\n"))
+                    ));
+                }
+            }
+            if let Some(log_path) = &tool.log_path {
+                xml.push_str(&format!(
+                    "    <system-out>Log: {}</system-out>\n",
+                    escape_xml(&log_path.to_string_lossy())
+                ));
+            }
+            xml.push_str("  </testcase>\n");
+        }
+        xml.push_str("</testsuite>\n");
+        xml
+    }
+
+    fn to_markdown(&self) -> String {
+        let mut md = String::from("# Toolkit Installation Report\n\n");
+        md.push_str(&format!(
+            "- Manifest: `{}`\n- Platform: {} ({})\n- Duration: {:.1}s\n- Result: {} of {} tools succeeded\n\n",
+            self.manifest,
+            self.os,
+            self.arch,
+            self.duration.as_secs_f64(),
+            self.tools.iter().filter(|tool| tool.status.is_success()).count(),
+            self.tools.len()
+        ));
+        md.push_str("| Tool | Status | Duration | Downloaded | Error |\n");
+        md.push_str("| --- | --- | --- | --- | --- |\n");
+        for tool in &self.tools {
+            md.push_str(&format!(
+                "| {} | {} | {:.1}s | {:.1} MiB | {} |\n",
+                escape_markdown(&tool.name),
+                tool.status,
+                tool.duration.as_secs_f64(),
+                tool.bytes_downloaded as f64 / 1024.0 / 1024.0,
+                escape_markdown(&tool.reason())
+            ));
+        }
+        if !self.hooks.is_empty() {
+            md.push_str("\n| Hook | Status | Duration | Error |\n");
+            md.push_str("| --- | --- | --- | --- |\n");
+            for hook in &self.hooks {
+                md.push_str(&format!(
+                    "| {} | {} | {:.1}s | {} |\n",
+                    hook.name,
+                    hook.status,
+                    hook.duration.as_secs_f64(),
+                    escape_markdown(&hook.reason())
+                ));
+            }
+        }
+        let failed_hooks = self.hooks.iter().filter(|hook| hook.status == HookStatus::Failed);
+        for hook in failed_hooks {
+            md.push_str(&format!(
+                "\n## hooks.{}\n\n{}\n",
+                hook.name,
+                fence_markdown(&hook.errors.join("\n"))
+            ));
+            if let Some(log_path) = &hook.log_path {
+                md.push_str(&format!("\nLog: `{}`\n", log_path.display()));
+            }
+        }
+        let failed_tools = self.tools.iter().filter(|tool| tool.status.is_failure());
+        for tool in failed_tools {
+            md.push_str(&format!(
+                "\n## {}\n\n{}\n",
+                tool.name,
+                fence_markdown(&tool.errors.join("\n"))
+            ));
+            if let Some(log_path) = &tool.log_path {
+                md.push_str(&format!("\nLog: `{}`\n", log_path.display()));
+            }
+        }
+        md
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, ValueEnum)]
+pub enum ReportFormat {
+    Json,
+    Junit,
+    Markdown,
+}
+
+fn serialize_duration_ms<S: serde::Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(duration.as_millis() as u64)
+}
+
+fn is_junit_skipped(status: ToolInstallationStatus) -> bool {
+    matches!(
+        status,
+        ToolInstallationStatus::Unsupported
+            | ToolInstallationStatus::Skipped
+            | ToolInstallationStatus::Cancelled
+            | ToolInstallationStatus::Interrupted
+            | ToolInstallationStatus::NotSelected
+    )
+}
+
+/// Escapes the value for XML 1.0, without the ANSI colors of command outputs and the control characters which XML 1.0
+/// forbids even escaped.
+fn escape_xml(value: &str) -> String {
+    strip_ansi_codes(value)
+        .chars()
+        .filter(|c| matches!(c, '\t' | '\n' | '\r' | '\u{20}'..='\u{D7FF}' | '\u{E000}'..='\u{FFFD}' | '\u{10000}'..))
+        .collect::<String>()
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn escape_markdown(value: &str) -> String {
+    strip_ansi_codes(value).replace('|', "\\|").replace('\n', " ")
+}
+
+/// Returns the content in a fenced code block whose fence is longer than any run of backticks of the content.
+fn fence_markdown(content: &str) -> String {
+    let content = strip_ansi_codes(content);
+    let longest_run = content.split(|c| c != '`').map(str::len).max().unwrap_or_default();
+    let fence = "`".repeat(longest_run.max(2) + 1);
+    format!("{}\n{}\n{}", fence, content, fence)
+}
+
+#[cfg(test)]
+mod test_report {
+    use super::*;
+
+    fn create_report() -> InstallationReport {
+        InstallationReport::new(
+            "./fixtures/toolkits.manifest.json",
+            Duration::from_millis(3200),
+            vec![
+                ToolInstallationResult {
+                    name: "Google Chrome".to_string(),
+                    status: ToolInstallationStatus::Installed,
+                    duration: Duration::from_millis(2000),
+                    bytes_downloaded: 2 * 1024 * 1024,
+                    errors: vec![],
+                    log_path: None,
+                    locked_tool: None,
+                },
+                ToolInstallationResult {
+                    name: "fnm".to_string(),
+                    status: ToolInstallationStatus::Failed,
+                    duration: Duration::from_millis(1200),
+                    bytes_downloaded: 0,
+                    errors: vec!["Failed to run <script>".to_string(), "exit code 1".to_string()],
+                    log_path: Some(PathBuf::from("/tmp/fnm.log")),
+                    locked_tool: None,
+                },
+            ],
+        )
+    }
+
+    #[test]
+    fn test_render_json() -> Result<()> {
+        let report: serde_json::Value = serde_json::from_str(&create_report().render(ReportFormat::Json)?)?;
+        assert_eq!(report["durationMs"], 3200);
+        assert_eq!(report["tools"][0]["status"], "installed");
+        assert_eq!(report["tools"][0]["bytesDownloaded"], 2 * 1024 * 1024);
+        assert_eq!(report["tools"][1]["status"], "failed");
+        assert_eq!(report["tools"][1]["errors"][1], "exit code 1");
+        assert_eq!(report["tools"][1]["logPath"], "/tmp/fnm.log");
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_junit() -> Result<()> {
+        let junit = create_report().render(ReportFormat::Junit)?;
+        assert!(junit.contains(r#"tests="2" failures="1" skipped="0""#));
+        assert!(junit.contains(r#"<failure message="Failed to run &lt;script&gt;">"#));
+        assert!(junit.contains(r#"name="Google Chrome" time="2.000" />"#));
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_hooks() -> Result<()> {
+        let report = create_report().with_hooks(vec![
+            HookResult {
+                name: "beforeAll".to_string(),
+                status: HookStatus::Succeeded,
+                duration: Duration::from_millis(100),
+                errors: vec![],
+                log_path: None,
+            },
+            HookResult {
+                name: "afterAll".to_string(),
+                status: HookStatus::Failed,
+                duration: Duration::from_millis(100),
+                errors: vec![
+                    "The afterAll failed".to_string(),
+                    "`source ~/.profile` failed".to_string(),
+                ],
+                log_path: None,
+            },
+        ]);
+        assert_eq!(report.hook_failures(), 1);
+
+        let json: serde_json::Value = serde_json::from_str(&report.render(ReportFormat::Json)?)?;
+        assert_eq!(json["hooks"][1]["status"], "failed");
+
+        let junit = report.render(ReportFormat::Junit)?;
+        assert!(junit.contains(r#"tests="4" failures="2" skipped="0""#));
+        assert!(junit.contains(r#"<testcase classname="toolkit.hooks" name="beforeAll" time="0.100" />"#));
+
+        let markdown = report.render(ReportFormat::Markdown)?;
+        assert!(markdown.contains("| afterAll | failed | 0.1s | The afterAll failed |"));
+        assert!(markdown.contains("## hooks.afterAll"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_markdown() -> Result<()> {
+        let markdown = create_report().render(ReportFormat::Markdown)?;
+        assert!(markdown.contains("- Result: 1 of 2 tools succeeded"));
+        assert!(markdown.contains("| Google Chrome | installed | 2.0s | 2.0 MiB |  |"));
+        assert!(markdown.contains("## fnm"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_unselected_provider() -> Result<()> {
+        let mut report = create_report();
+        report.tools.push(ToolInstallationResult::unfinished(
+            "nvm",
+            ToolInstallationStatus::NotSelected,
+            "'node' is provided by fnm",
+            Duration::ZERO,
+        ));
+        assert_eq!(report.failures(), 1);
+        assert_eq!(
+            report.tools[2].to_string(),
+            format!("{} nvm: Skipped. Reason: 'node' is provided by fnm", WARNING_EMOJI)
+        );
+
+        let json: serde_json::Value = serde_json::from_str(&report.render(ReportFormat::Json)?)?;
+        assert_eq!(json["tools"][2]["status"], "notSelected");
+        assert_eq!(json["tools"][2]["errors"][0], "'node' is provided by fnm");
+
+        let junit = report.render(ReportFormat::Junit)?;
+        assert!(junit.contains(r#"tests="3" failures="1" skipped="1""#));
+
+        let markdown = report.render(ReportFormat::Markdown)?;
+        assert!(markdown.contains("| nvm | not selected | 0.0s | 0.0 MiB | 'node' is provided by fnm |"));
+        assert!(!markdown.contains("## nvm"));
+        // the provider which was not selected did not succeed either
+        assert!(markdown.contains("- Result: 1 of 3 tools succeeded"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_command_output() -> Result<()> {
+        let mut report = create_report();
+        report.tools[1].errors = vec![
+            "`npm install` failed".to_string(),
+            "\x1b[31mnpm ERR!\x1b[0m code E404\x07".to_string(),
+            "```\nnot closed".to_string(),
+        ];
+
+        let junit = report.render(ReportFormat::Junit)?;
+        assert!(junit.contains("npm ERR! code E404\n"), "{}", junit);
+        assert!(!junit
+            .chars()
+            .any(|c| c.is_control() && !matches!(c, '\t' | '\n' | '\r')));
+
+        let markdown = report.render(ReportFormat::Markdown)?;
+        assert!(markdown.contains("````\n`npm install` failed\nnpm ERR! code E404\u{7}\n```\nnot closed\n````\n"));
+        Ok(())
+    }
+}
@@ -0,0 +1,76 @@
+use anyhow::Result;
+use reqwest::Client;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    installation::filter_tool_installation_detail, resolve_toolkits_manifest, ManifestError, ManifestOptions,
+    ManifestScriptOpts, ShellScript,
+};
+
+/// Returns the verified install script of a tool for the current platform, so that it can be reviewed before the
+/// installation.
+pub async fn get_tool_script(opts: &ManifestScriptOpts) -> Result<String> {
+    let manifest_path = opts.source.manifest.join(", ");
+    let manifest_options = ManifestOptions::new(opts.source.offline, opts.source.insecure_skip_verify)
+        .map_err(|err| ManifestError::new(&manifest_path, err))?;
+    let toolkits_manifest = resolve_toolkits_manifest(&opts.source.manifest, &manifest_options)
+        .await
+        .map_err(|err| ManifestError::new(&manifest_path, err))?;
+    let tools_installation_detail = filter_tool_installation_detail(&toolkits_manifest.toolkits)?;
+    let tool = tools_installation_detail
+        .iter()
+        .find(|tool| tool.name == opts.name)
+        .ok_or_else(|| anyhow::anyhow!("No installation of '{}' for the current platform", opts.name))?;
+    let script = tool
+        .candidates()
+        .find_map(|candidate| candidate.script.as_ref())
+        .ok_or_else(|| anyhow::anyhow!("The installation of '{}' has no `script`", opts.name))?;
+    fetch_script(&Client::new(), script).await
+}
+
+/// Downloads the script and verifies its pinned sha256 checksum.
+pub async fn fetch_script(client: &Client, script: &ShellScript) -> Result<String> {
+    let response = client
+        .get(&script.url)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|err| anyhow::anyhow!("Failed to download the script '{}'. Error: {}", script.url, err))?;
+    let content = response.bytes().await?;
+    verify_checksum(script, &content)?;
+    String::from_utf8(content.to_vec()).map_err(|_| anyhow::anyhow!("The script '{}' is not valid UTF-8", script.url))
+}
+
+fn verify_checksum(script: &ShellScript, content: &[u8]) -> Result<()> {
+    let checksum = format!("{:x}", Sha256::digest(content));
+    if !checksum.eq_ignore_ascii_case(script.sha256.trim()) {
+        return Err(anyhow::anyhow!(
+            "The sha256 checksum of the script '{}' is {}, but {} is pinned in the manifest",
+            script.url,
+            checksum,
+            script.sha256
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test_script {
+    use super::*;
+
+    #[test]
+    fn test_verify_checksum() {
+        let mut script = ShellScript {
+            url: "https://fnm.vercel.app/install".to_string(),
+            sha256: "2CF24DBA5FB0A30E26E83B2AC5B9E29E1B161E5C1FA7425E73043362938B9824".to_string(),
+            args: vec![],
+        };
+        assert!(verify_checksum(&script, b"hello").is_ok());
+
+        script.sha256 = "0".repeat(64);
+        let err = verify_checksum(&script, b"hello").unwrap_err();
+        assert!(err.to_string().starts_with(
+            "The sha256 checksum of the script 'https://fnm.vercel.app/install' is 2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        ));
+    }
+}
@@ -0,0 +1,93 @@
+use anyhow::Result;
+use std::{
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+use tokio::{fs, io::AsyncWriteExt};
+
+use crate::{
+    installation::script::fetch_script, quote_shell_arg, run_command_steps, Artifact, CommandOptions, CommandRunner,
+    InstallContext, InstallStatus, InstallationDetailItem, RetriableError, UndoAction,
+};
+
+/// Installs a `shell` installation by running its `source` command, or its pinned `script` with bash. Shared by the
+/// unix platforms.
+pub async fn install_tool_by_shell(
+    tool_installation_detail: &InstallationDetailItem,
+    ctx: &InstallContext,
+) -> Result<InstallStatus> {
+    if ctx.runner().exists(&tool_installation_detail.id).await? {
+        return Ok(InstallStatus::AlreadyInstalled);
+    }
+
+    // file systems store the modification time in whole seconds at worst
+    let started_at = SystemTime::now() - Duration::from_secs(1);
+    match &tool_installation_detail.script {
+        Some(script) => {
+            ctx.set_message("Downloading the install script...");
+            let content = fetch_script(ctx.http_client(), script)
+                .await
+                .map_err(RetriableError::wrap)?;
+            ctx.record_artifact(Artifact {
+                url: script.url.clone(),
+                sha256: script.sha256.to_lowercase(),
+                size: content.len() as u64,
+            })?;
+            // the directory is only accessible by the user, so that the verified script cannot be replaced before it
+            // runs, and it is removed when it is dropped, whatever the result of the script
+            let script_dir = tempfile::Builder::new().prefix("toolkit-script-").tempdir()?;
+            let script_path = script_dir.path().join("install.sh");
+            let mut script_file = fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&script_path)
+                .await?;
+            script_file.write_all(content.as_bytes()).await?;
+            script_file.flush().await?;
+            drop(script_file);
+            let command = std::iter::once(script_path.to_string_lossy().as_ref())
+                .chain(script.args.iter().map(String::as_str))
+                .map(quote_shell_arg)
+                .collect::<Vec<_>>()
+                .join(" ");
+            ctx.run_streaming(&format!("bash {}", command))
+                .await
+                .map_err(RetriableError::wrap)?;
+        }
+        None => {
+            ctx.run_streaming(&tool_installation_detail.source)
+                .await
+                .map_err(RetriableError::wrap)?;
+        }
+    }
+
+    // the command is removed when a later step fails, but only when the installer wrote it: it may also be an existing
+    // binary which the installer only added to the `PATH`, e.g. of a package manager
+    if let Some(path) = locate_command(ctx.runner(), &tool_installation_detail.id).await {
+        match std::fs::symlink_metadata(&path).and_then(|metadata| metadata.modified()) {
+            Ok(modified) if modified >= started_at => {
+                ctx.register_undo(UndoAction::RemoveInstalledFile { path, modified });
+            }
+            _ => ctx.log(&format!(
+                "'{}' existed before the installation and is not removed on rollback",
+                path.display()
+            )),
+        }
+    }
+
+    if let Some(post_install) = &tool_installation_detail.post_install {
+        run_command_steps("post-install", post_install, ctx).await?;
+    }
+
+    Ok(InstallStatus::Installed)
+}
+
+/// Returns the file of the command on the `PATH` of the user's shell, unless it is a function or an alias.
+async fn locate_command(runner: &dyn CommandRunner, command: &str) -> Option<PathBuf> {
+    let program = format!("command -v {}", quote_shell_arg(command));
+    let output = runner.run(&program, &CommandOptions::default()).await.ok()?;
+    // the rc file of the user's shell may print before the path
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let path = PathBuf::from(stdout.lines().last()?.trim());
+    (output.status.success() && path.is_absolute() && path.is_file()).then_some(path)
+}
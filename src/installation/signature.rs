@@ -0,0 +1,80 @@
+use anyhow::Result;
+use minisign_verify::{PublicKey, Signature};
+use std::fs;
+
+use crate::{utils::get_config_dir, Config};
+
+/// Loads the minisign public keys trusted to sign remote manifests: the `trusted_keys` of the user configuration and
+/// the keys in `<config dir>/trusted_keys`, one key per line. Empty lines, `#` comments and the `untrusted comment:`
+/// lines of minisign `.pub` files are ignored.
+pub fn load_trusted_keys() -> Result<Vec<String>> {
+    let mut trusted_keys = Config::load()?.trusted_keys();
+    let path = get_config_dir().join("trusted_keys");
+    if path.exists() {
+        let content = fs::read_to_string(&path)
+            .map_err(|err| anyhow::anyhow!("Failed to read the trusted keys '{}'. Error: {}", path.display(), err))?;
+        trusted_keys.extend(parse_trusted_keys(&content));
+    }
+    Ok(trusted_keys)
+}
+
+fn parse_trusted_keys(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with("untrusted comment:"))
+        .map(|line| line.to_string())
+        .collect()
+}
+
+/// Verifies the detached minisign signature of a manifest against the trusted public keys.
+pub fn verify_manifest_signature(content: &str, signature: &str, trusted_keys: &[String]) -> Result<()> {
+    if trusted_keys.is_empty() {
+        return Err(anyhow::anyhow!(
+            "No trusted keys are configured. Add the minisign public key of the manifest author to '{}'",
+            get_config_dir().join("trusted_keys").display()
+        ));
+    }
+    let signature = Signature::decode(signature)
+        .map_err(|err| anyhow::anyhow!("Failed to decode the signature. Error: {}", err))?;
+    for trusted_key in trusted_keys {
+        let public_key = PublicKey::from_base64(trusted_key)
+            .map_err(|err| anyhow::anyhow!("Invalid trusted key '{}'. Error: {}", trusted_key, err))?;
+        if public_key.verify(content.as_bytes(), &signature, false).is_ok() {
+            return Ok(());
+        }
+    }
+    Err(anyhow::anyhow!(
+        "The signature does not match the manifest with any trusted key"
+    ))
+}
+
+#[cfg(test)]
+mod test_signature {
+    use super::*;
+
+    fn read_fixture(name: &str) -> String {
+        fs::read_to_string(format!("./fixtures/signing/{}", name)).unwrap()
+    }
+
+    #[test]
+    fn test_verify_manifest_signature() {
+        let content = read_fixture("toolkits.manifest.json");
+        let signature = read_fixture("toolkits.manifest.json.minisig");
+        let trusted_keys = parse_trusted_keys(&read_fixture("toolkit.pub"));
+        assert_eq!(trusted_keys.len(), 1);
+
+        assert!(verify_manifest_signature(&content, &signature, &trusted_keys).is_ok());
+
+        let tampered = content.replace("fnm.vercel.app", "evil.example.com");
+        let err = verify_manifest_signature(&tampered, &signature, &trusted_keys).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "The signature does not match the manifest with any trusted key"
+        );
+
+        let other_key = "RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3".to_string();
+        assert!(verify_manifest_signature(&content, &signature, &[other_key]).is_err());
+        assert!(verify_manifest_signature(&content, &signature, &[]).is_err());
+    }
+}
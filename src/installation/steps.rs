@@ -0,0 +1,115 @@
+use anyhow::Result;
+
+use crate::{run_checked, CommandSteps, InstallContext};
+
+/// Runs the steps in order. A step which fails stops the remaining steps and fails the installation, unless it has
+/// `continueOnError`.
+pub async fn run_command_steps(label: &str, steps: &CommandSteps, ctx: &InstallContext) -> Result<()> {
+    let steps = steps.to_steps();
+    for (index, step) in steps.iter().enumerate() {
+        let name = if steps.len() > 1 {
+            format!("{} step {}/{}", label, index + 1, steps.len())
+        } else {
+            label.to_string()
+        };
+        ctx.set_message(&format!("Running {}...", name));
+        let mut options = step.options();
+        if step.timeout.is_none() {
+            options.timeout = ctx.timeout();
+        }
+        let result = if ctx.is_interactive() {
+            ctx.run_interactive(&step.run, &options).await
+        } else {
+            ctx.log(&format!("$ {}", step.run));
+            run_checked(ctx.runner(), &step.run, &options).await.map(|output| {
+                for stream in [&output.stdout, &output.stderr] {
+                    let text = String::from_utf8_lossy(stream);
+                    if !text.trim().is_empty() {
+                        ctx.log(text.trim_end());
+                    }
+                }
+            })
+        };
+        match result {
+            Ok(()) => {}
+            Err(err) if step.continue_on_error => {
+                ctx.log(&format!(
+                    "The {} failed and is ignored (continueOnError): {:?}",
+                    name, err
+                ));
+            }
+            Err(err) => return Err(err.context(format!("The {} failed", name))),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+#[cfg(test)]
+mod test_steps {
+    use super::*;
+    use crate::CommandStep;
+    use indicatif::ProgressBar;
+    use std::{collections::BTreeMap, env, fs, time::Duration};
+
+    fn create_step(run: &str) -> CommandStep {
+        CommandStep {
+            run: run.to_string(),
+            shell: Some("sh".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_command_steps() -> Result<()> {
+        let ctx = InstallContext::new("test-steps", ProgressBar::hidden());
+        let dir = env::temp_dir();
+        let output_path = dir.join(format!("toolkit-test-steps-{}", std::process::id()));
+        let steps = CommandSteps::Steps(vec![
+            CommandStep {
+                env: BTreeMap::from([("NODE_VERSION".to_string(), "22".to_string())]),
+                cwd: Some(dir.to_string_lossy().to_string()),
+                ..create_step(&format!(
+                    "echo $NODE_VERSION $(pwd) > {}",
+                    output_path.file_name().unwrap().to_string_lossy()
+                ))
+            },
+            CommandStep {
+                continue_on_error: true,
+                ..create_step("exit 3")
+            },
+        ]);
+        run_command_steps("post-install", &steps, &ctx).await?;
+        let output = fs::read_to_string(&output_path)?;
+        assert!(output.starts_with("22 "), "{}", output);
+        fs::remove_file(&output_path)?;
+
+        let steps = CommandSteps::Steps(vec![
+            create_step("echo 'fnm: command not found' >&2; exit 127"),
+            create_step("echo unreachable"),
+        ]);
+        let err = run_command_steps("post-install", &steps, &ctx).await.unwrap_err();
+        let errors = err.chain().map(|cause| cause.to_string()).collect::<Vec<_>>();
+        assert_eq!(errors[0], "The post-install step 1/2 failed");
+        assert!(errors[1].ends_with(": fnm: command not found"), "{}", errors[1]);
+
+        let steps = CommandSteps::Steps(vec![CommandStep {
+            timeout: Some(1),
+            ..create_step("sleep 5")
+        }]);
+        let err = run_command_steps("post-install", &steps, &ctx).await.unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            "The post-install failed: `sleep 5` timed out after 1s"
+        );
+
+        // a timeout of 0 disables the timeout of the installation
+        let ctx = InstallContext::new("test-steps", ProgressBar::hidden()).with_timeout(Some(Duration::from_secs(1)));
+        let steps = CommandSteps::Steps(vec![CommandStep {
+            timeout: Some(0),
+            ..create_step("sleep 1.5")
+        }]);
+        run_command_steps("post-install", &steps, &ctx).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,269 @@
+use anyhow::Result;
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+use std::{collections::BTreeMap, env};
+
+use crate::{quote_shell_arg, CommandSteps, Config, ToolkitsManifest};
+
+lazy_static! {
+    /// A `{{name}}` or `{{name | filter}}`, or a `\{{` which is kept as a literal `{{`.
+    static ref VARIABLE_REGEX: Regex =
+        Regex::new(r"\\\{\{|\{\{\s*([A-Za-z_][A-Za-z0-9_]*)\s*(?:\|\s*([A-Za-z_][A-Za-z0-9_]*)\s*)?\}\}").unwrap();
+}
+
+/// The variables available in every manifest: `os`, `arch`, `home`, `prefix` and `bin_dir`.
+pub fn get_builtin_vars() -> BTreeMap<String, String> {
+    let home = home::home_dir().unwrap_or_default();
+    // an invalid config is reported when the command line is parsed
+    let prefix = Config::load().unwrap_or_default().prefix();
+    let bin_dir = prefix.join("bin");
+    BTreeMap::from([
+        ("os".to_string(), env::consts::OS.to_string()),
+        ("arch".to_string(), env::consts::ARCH.to_string()),
+        ("home".to_string(), home.to_string_lossy().to_string()),
+        ("prefix".to_string(), prefix.to_string_lossy().to_string()),
+        ("bin_dir".to_string(), bin_dir.to_string_lossy().to_string()),
+    ])
+}
+
+/// Replaces every `{{name}}` in the template with the value of the variable, as it is, and every `{{name | quote}}`
+/// with the value quoted for a POSIX shell. A `\{{` is replaced with a literal `{{`. Returns the names of the unknown
+/// variables and filters as the error.
+pub fn expand_template(template: &str, vars: &BTreeMap<String, String>) -> Result<String, Vec<String>> {
+    let mut unknown_vars = vec![];
+    let expanded = VARIABLE_REGEX.replace_all(template, |captures: &Captures| {
+        let Some(name) = captures.get(1) else {
+            return "{{".to_string();
+        };
+        let value = vars.get(name.as_str());
+        match (value, captures.get(2).map(|filter| filter.as_str())) {
+            (Some(value), None) => value.clone(),
+            (Some(value), Some("quote")) => quote_shell_arg(value),
+            (Some(_), Some(filter)) => {
+                unknown_vars.push(format!("{} | {}", name.as_str(), filter));
+                captures[0].to_string()
+            }
+            (None, _) => {
+                unknown_vars.push(name.as_str().to_string());
+                captures[0].to_string()
+            }
+        }
+    });
+    if unknown_vars.is_empty() {
+        Ok(expanded.to_string())
+    } else {
+        Err(unknown_vars)
+    }
+}
+
+/// Expands the variables in the `source`, `id`, `script.url`, `preInstall` and `postInstall` of every installation
+/// and in the manifest `hooks`.
+///
+/// The toolkit `vars` and `version` take precedence over the manifest `vars`, which take precedence over the
+/// built-in variables. The values of `vars` may use the built-in variables.
+pub fn expand_manifest_vars(manifest: &mut ToolkitsManifest) -> Result<()> {
+    expand_vars(manifest, &get_builtin_vars())
+}
+
+fn expand_vars(manifest: &mut ToolkitsManifest, builtin_vars: &BTreeMap<String, String>) -> Result<()> {
+    let mut unknown_vars: Vec<String> = vec![];
+    let mut expand = |template: &mut String, vars: &BTreeMap<String, String>, location: &str| match expand_template(
+        template, vars,
+    ) {
+        Ok(expanded) => *template = expanded,
+        Err(names) => unknown_vars.extend(names.iter().map(|name| format!("`{}` ({})", name, location))),
+    };
+
+    let mut manifest_vars = builtin_vars.clone();
+    for (name, value) in &manifest.vars {
+        let mut value = value.clone();
+        expand(&mut value, builtin_vars, &format!("vars.{}", name));
+        manifest_vars.insert(name.clone(), value);
+    }
+
+    for (name, steps) in [
+        ("beforeAll", manifest.hooks.before_all.as_mut()),
+        ("afterAll", manifest.hooks.after_all.as_mut()),
+    ] {
+        for (path, template) in steps.into_iter().flat_map(CommandSteps::templates_mut) {
+            expand(template, &manifest_vars, &format!("hooks.{}{}", name, path));
+        }
+    }
+
+    for (tool_index, tool) in manifest.toolkits.iter_mut().enumerate() {
+        let mut tool_vars = manifest_vars.clone();
+        for (name, value) in &tool.vars {
+            let mut value = value.clone();
+            expand(
+                &mut value,
+                builtin_vars,
+                &format!("toolkits[{}].vars.{}", tool_index, name),
+            );
+            tool_vars.insert(name.clone(), value);
+        }
+        if let Some(version) = &tool.version {
+            tool_vars.insert("version".to_string(), version.clone());
+        }
+
+        for (installation_index, installation) in tool.installations.iter_mut().enumerate() {
+            let location = format!("toolkits[{}].installations[{}]", tool_index, installation_index);
+            expand(&mut installation.source, &tool_vars, &format!("{}.source", location));
+            expand(&mut installation.id, &tool_vars, &format!("{}.id", location));
+            if let Some(script) = installation.script.as_mut() {
+                expand(&mut script.url, &tool_vars, &format!("{}.script.url", location));
+            }
+            for (name, steps) in [
+                ("preInstall", installation.pre_install.as_mut()),
+                ("postInstall", installation.post_install.as_mut()),
+            ] {
+                for (path, template) in steps.into_iter().flat_map(CommandSteps::templates_mut) {
+                    expand(template, &tool_vars, &format!("{}.{}{}", location, name, path));
+                }
+            }
+        }
+    }
+
+    if unknown_vars.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!("Unknown variables: {}", unknown_vars.join(", ")))
+    }
+}
+
+#[cfg(test)]
+mod test_template {
+    use super::*;
+
+    fn create_manifest(value: serde_json::Value) -> ToolkitsManifest {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_expand_template() {
+        let vars = BTreeMap::from([("version".to_string(), "1.89.1".to_string())]);
+        assert_eq!(
+            expand_template("https://update.code.visualstudio.com/{{version}}/darwin/stable", &vars),
+            Ok("https://update.code.visualstudio.com/1.89.1/darwin/stable".to_string())
+        );
+        assert_eq!(
+            expand_template("{{ version }}-{{ channel }}", &vars),
+            Err(vec!["channel".to_string()])
+        );
+
+        // a variable is inserted as it is unless it is quoted with the `quote` filter
+        let vars = BTreeMap::from([("home".to_string(), "/home/John Doe".to_string())]);
+        assert_eq!(
+            expand_template("ls \"{{home}}/.nvm\" {{ home | quote }}/.fnm", &vars),
+            Ok("ls \"/home/John Doe/.nvm\" '/home/John Doe'/.fnm".to_string())
+        );
+        assert_eq!(
+            expand_template("echo \\{{home}}", &vars),
+            Ok("echo {{home}}".to_string())
+        );
+        assert_eq!(
+            expand_template("{{home | upper}}", &vars),
+            Err(vec!["home | upper".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_expand_manifest_vars() -> Result<()> {
+        let mut manifest = create_manifest(serde_json::json!({
+            "author": "",
+            "version": "",
+            "description": "",
+            "vars": { "channel": "stable", "node": "22" },
+            "toolkits": [{
+                "name": "Visual Studio Code",
+                "description": "",
+                "version": "1.89.1",
+                "vars": { "channel": "insider" },
+                "installations": [{
+                    "os": "macos",
+                    "type": "zip",
+                    "id": "Visual Studio Code.app",
+                    "source": "https://update.code.visualstudio.com/{{version}}/darwin/{{channel}}",
+                    "postInstall": "echo {{os}} {{node}}"
+                }]
+            }]
+        }));
+        expand_manifest_vars(&mut manifest)?;
+        let installation = &manifest.toolkits[0].installations[0];
+        assert_eq!(
+            installation.source,
+            "https://update.code.visualstudio.com/1.89.1/darwin/insider"
+        );
+        assert_eq!(
+            installation.post_install.as_ref().map(CommandSteps::commands),
+            Some(vec![format!("echo {} 22", env::consts::OS).as_str()])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_report_unknown_vars() {
+        let mut manifest = create_manifest(serde_json::json!({
+            "author": "",
+            "version": "",
+            "description": "",
+            "toolkits": [{
+                "name": "fnm",
+                "description": "",
+                "installations": [{
+                    "os": "macos",
+                    "type": "shell",
+                    "id": "fnm",
+                    "source": "curl {{url}} | bash",
+                    "postInstall": "fnm install {{version}}"
+                }]
+            }]
+        }));
+        let err = expand_manifest_vars(&mut manifest).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unknown variables: `url` (toolkits[0].installations[0].source), `version` (toolkits[0].installations[0].postInstall)"
+        );
+    }
+
+    #[test]
+    fn test_expand_vars_verbatim() -> Result<()> {
+        let mut manifest = create_manifest(serde_json::json!({
+            "author": "",
+            "version": "",
+            "description": "",
+            "hooks": { "afterAll": "ls {{bin_dir | quote}}" },
+            "toolkits": [{
+                "name": "fnm",
+                "description": "",
+                "vars": { "fnm_dir": "{{home}}/.fnm" },
+                "installations": [{
+                    "os": "linux",
+                    "type": "shell",
+                    "id": "fnm",
+                    "source": "curl -fsSL https://fnm.vercel.app/install | bash -s -- --install-dir \"{{fnm_dir}}\"",
+                    "postInstall": [{ "run": "fnm env --fnm-dir {{fnm_dir | quote}}", "cwd": "{{home}}" }]
+                }]
+            }]
+        }));
+        let builtin_vars = BTreeMap::from([
+            ("home".to_string(), "/home/John Doe".to_string()),
+            ("bin_dir".to_string(), "/home/John Doe/.local/bin".to_string()),
+        ]);
+        expand_vars(&mut manifest, &builtin_vars)?;
+        assert_eq!(
+            manifest.hooks.after_all.as_ref().map(CommandSteps::commands),
+            Some(vec!["ls '/home/John Doe/.local/bin'"])
+        );
+        let installation = &manifest.toolkits[0].installations[0];
+        assert_eq!(
+            installation.source,
+            "curl -fsSL https://fnm.vercel.app/install | bash -s -- --install-dir \"/home/John Doe/.fnm\""
+        );
+        let Some(CommandSteps::Steps(steps)) = &installation.post_install else {
+            panic!("expected the postInstall steps");
+        };
+        assert_eq!(steps[0].run, "fnm env --fnm-dir '/home/John Doe/.fnm'");
+        assert_eq!(steps[0].cwd.as_deref(), Some("/home/John Doe"));
+        Ok(())
+    }
+}
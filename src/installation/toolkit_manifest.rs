@@ -1,61 +1,280 @@
 use anyhow::Result;
-use path_absolutize::*;
-use std::{env, path::Path, str::FromStr};
-use tokio::fs;
+use futures::future::BoxFuture;
+use std::{
+    cmp::Reverse,
+    env,
+    error::Error,
+    fmt::{self, Display},
+    path::PathBuf,
+    str::FromStr,
+};
 
-use crate::{Arch, InstallationDetailItem, ToolInstallationManifest, ToolkitsManifest, OS};
+use crate::{
+    installation::{
+        compose::{merge_manifests, resolve_manifest_reference},
+        dependency::validate_dependencies,
+        manifest_cache::get_manifest_cache_dir,
+        manifest_source::ManifestSource,
+        signature::{load_trusted_keys, verify_manifest_signature},
+        template::expand_manifest_vars,
+    },
+    utils::WARNING_EMOJI,
+    Arch, InstallationDetailItem, RawInstallationDetailItem, ToolInstallationManifest, ToolkitsManifest, Type, OS,
+};
+
+/// An error caused by the toolkits manifest rather than by the installation of a tool.
+#[derive(Debug)]
+pub struct ManifestError {
+    manifest_path: String,
+    source: anyhow::Error,
+}
+
+impl ManifestError {
+    pub fn new(manifest_path: &str, source: anyhow::Error) -> Self {
+        Self {
+            manifest_path: manifest_path.to_string(),
+            source,
+        }
+    }
+}
+
+impl Display for ManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid toolkits manifest '{}'", self.manifest_path)
+    }
+}
+
+impl Error for ManifestError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
+
+/// How the manifests are loaded.
+#[derive(Debug, Clone)]
+pub struct ManifestOptions {
+    /// Use the cached copies of remote manifests without fetching them.
+    pub offline: bool,
+    pub cache_dir: PathBuf,
+    /// Use remote manifests without verifying their signatures.
+    pub insecure_skip_verify: bool,
+    /// The minisign public keys trusted to sign remote manifests.
+    pub trusted_keys: Vec<String>,
+}
+
+impl ManifestOptions {
+    /// Creates the options with the trusted keys of the user configuration.
+    pub fn new(offline: bool, insecure_skip_verify: bool) -> Result<Self> {
+        Ok(Self {
+            offline,
+            insecure_skip_verify,
+            trusted_keys: load_trusted_keys()?,
+            ..Default::default()
+        })
+    }
+}
+
+impl Default for ManifestOptions {
+    fn default() -> Self {
+        Self {
+            offline: false,
+            cache_dir: get_manifest_cache_dir(),
+            insecure_skip_verify: false,
+            trusted_keys: vec![],
+        }
+    }
+}
 
 pub async fn get_tookits_manifest(manifest_path: &str) -> Result<ToolkitsManifest> {
-    let manifest = if manifest_path.starts_with("http") {
-        let manifest: ToolkitsManifest = reqwest::get(manifest_path).await?.json().await?;
-        manifest
-    } else {
-        let json = fs::read_to_string(Path::new(manifest_path).absolutize()?).await?;
-        let manifest: ToolkitsManifest = serde_json::from_str(&json)?;
-        manifest
-    };
+    resolve_toolkits_manifest(&[manifest_path.to_string()], &ManifestOptions::default()).await
+}
+
+/// Loads the manifests with their `extends` and `include`, layers them in order and expands their variables.
+pub async fn resolve_toolkits_manifest(
+    manifest_paths: &[String],
+    options: &ManifestOptions,
+) -> Result<ToolkitsManifest> {
+    let mut manifest = ToolkitsManifest::default();
+    let mut is_stdin_read = false;
+    for manifest_path in manifest_paths {
+        let layer = load_composed_manifest(manifest_path, options, &mut vec![], &mut is_stdin_read).await?;
+        manifest = merge_manifests(manifest, layer);
+    }
+    expand_manifest_vars(&mut manifest)?;
+    validate_installation_sources(&manifest.toolkits)?;
+    validate_dependencies(&manifest.toolkits)?;
 
     Ok(manifest)
 }
+
+fn load_composed_manifest<'a>(
+    manifest_path: &'a str,
+    options: &'a ManifestOptions,
+    loading_paths: &'a mut Vec<(String, String)>,
+    is_stdin_read: &'a mut bool,
+) -> BoxFuture<'a, Result<ToolkitsManifest>> {
+    Box::pin(async move {
+        let source = ManifestSource::parse(manifest_path)?;
+        // the standard input is consumed by the first read, so a second `-` would silently load an empty manifest
+        if source == ManifestSource::Stdin {
+            if *is_stdin_read {
+                return Err(anyhow::anyhow!("The manifest `-` can only be read once from stdin"));
+            }
+            *is_stdin_read = true;
+        }
+        let key = source.key()?;
+        if let Some(start) = loading_paths.iter().position(|(loading_key, _)| *loading_key == key) {
+            let mut cycle = loading_paths[start..]
+                .iter()
+                .map(|(_, path)| path.as_str())
+                .collect::<Vec<_>>();
+            cycle.push(manifest_path);
+            return Err(anyhow::anyhow!(
+                "Manifest include cycle detected: {}",
+                cycle.join(" -> ")
+            ));
+        }
+        loading_paths.push((key, manifest_path.to_string()));
+
+        let mut own_manifest = load_manifest(manifest_path, options).await?;
+        let references = own_manifest
+            .extends
+            .take()
+            .into_iter()
+            .chain(std::mem::take(&mut own_manifest.include));
+        let mut manifest = ToolkitsManifest::default();
+        for reference in references {
+            let reference_path = resolve_manifest_reference(manifest_path, &reference)?;
+            let layer = load_composed_manifest(&reference_path, options, loading_paths, is_stdin_read)
+                .await
+                .map_err(|err| err.context(format!("Failed to load '{}' from '{}'", reference, manifest_path)))?;
+            manifest = merge_manifests(manifest, layer);
+        }
+        loading_paths.pop();
+
+        Ok(merge_manifests(manifest, own_manifest))
+    })
+}
+
+async fn load_manifest(manifest_path: &str, options: &ManifestOptions) -> Result<ToolkitsManifest> {
+    let source = ManifestSource::parse(manifest_path)?;
+    let (content, format) = source.read(options).await?;
+    if source.is_remote() {
+        if options.insecure_skip_verify {
+            eprintln!(
+                "{} Skipping the signature verification of '{}'",
+                WARNING_EMOJI, manifest_path
+            );
+        } else {
+            verify_remote_manifest(&source, &content, options)
+                .await
+                .map_err(|err| {
+                    err.context(format!(
+                        "Refusing to use the unverified manifest '{}'. Pass --insecure-skip-verify to use it anyway",
+                        manifest_path
+                    ))
+                })?;
+        }
+    }
+    format.parse(&content)
+}
+
+async fn verify_remote_manifest(source: &ManifestSource, content: &str, options: &ManifestOptions) -> Result<()> {
+    let signature = source
+        .read_signature(options)
+        .await
+        .map_err(|err| err.context("Failed to read the signature"))?;
+    verify_manifest_signature(content, &signature, &options.trusted_keys)
+}
+
+/// Checks that every installation has either a `source` or, for shell installations, a `script` pinned by a sha256
+/// checksum.
+fn validate_installation_sources(tools: &[ToolInstallationManifest]) -> Result<()> {
+    let mut errors = vec![];
+    for (tool_index, tool) in tools.iter().enumerate() {
+        for (installation_index, installation) in tool.installations.iter().enumerate() {
+            let location = format!("toolkits[{}].installations[{}]", tool_index, installation_index);
+            match (&installation.script, installation.source.is_empty()) {
+                (Some(_), _) if installation.r#type != Type::Shell => errors.push(format!(
+                    "{}: `script` is only supported by shell installations",
+                    location
+                )),
+                (Some(_), false) => errors.push(format!("{}: `source` and `script` cannot be used together", location)),
+                (None, true) => errors.push(format!("{}: missing `source`", location)),
+                (Some(script), true) if !is_sha256(script.sha256.trim()) => errors.push(format!(
+                    "{}: `script.sha256` must be the 64 hex digits of a sha256 checksum",
+                    location
+                )),
+                _ => {}
+            }
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!(errors.join("\n")))
+    }
+}
+
+fn is_sha256(checksum: &str) -> bool {
+    checksum.len() == 64 && checksum.chars().all(|c| c.is_ascii_hexdigit())
+}
+
 // filter the toolkits based on the current OS and Arch
+// every tool gets one selected installation, the other matched installations are kept as its fallbacks
 pub fn filter_tool_installation_detail(
     tools_installation_manifest: &[ToolInstallationManifest],
 ) -> Result<Vec<InstallationDetailItem>> {
-    let cur_arch: &str = env::consts::ARCH;
-    let cur_os = env::consts::OS;
+    let cur_arch = Arch::from_str(env::consts::ARCH).expect("failed to convert `std::env::consts::ARCH` to Arch enum");
+    let cur_os = OS::from_str(env::consts::OS).expect("failed to convert `std::env::consts::OS` to OS enum");
 
     let mut filtered_tools_installation_detail: Vec<InstallationDetailItem> = vec![];
 
     tools_installation_manifest.iter().for_each(|tool| {
-        tool.installations.iter().for_each(|installation| {
-            let tool_installation_detail = InstallationDetailItem {
-                name: tool.name.clone(),
-                description: tool.description.clone(),
-                os: installation.os,
-                arch: installation.arch,
-                id: installation.id.clone(),
-                r#type: installation.r#type,
-                source: installation.source.clone(),
-                post_install: installation.post_install.clone(),
-            };
-            let is_os_matched =
-                installation.os == OS::from_str(cur_os).expect("failed to convert `std::env::consts::OS` to OS enum");
-            if let Some(arch) = &installation.arch {
-                if is_os_matched
-                    && *arch
-                        == Arch::from_str(cur_arch).expect("failed to convert `std::env::consts::ARCH` to Arch enum")
-                {
-                    filtered_tools_installation_detail.push(tool_installation_detail);
-                }
-            } else if is_os_matched {
-                filtered_tools_installation_detail.push(tool_installation_detail);
-            }
-        })
+        let mut candidates = tool
+            .installations
+            .iter()
+            .filter(|installation| {
+                installation.os == cur_os && !matches!(installation.arch, Some(arch) if arch != cur_arch)
+            })
+            .collect::<Vec<_>>();
+        // higher priority first, then the arch-specific installations before the arch-less ones
+        candidates.sort_by_key(|installation| (Reverse(installation.priority), installation.arch.is_none()));
+
+        let mut candidates = candidates
+            .into_iter()
+            .map(|installation| create_installation_detail_item(tool, installation));
+        if let Some(mut tool_installation_detail) = candidates.next() {
+            tool_installation_detail.fallbacks = candidates.collect();
+            filtered_tools_installation_detail.push(tool_installation_detail);
+        }
     });
 
     Ok(filtered_tools_installation_detail)
 }
 
+fn create_installation_detail_item(
+    tool: &ToolInstallationManifest,
+    installation: &RawInstallationDetailItem,
+) -> InstallationDetailItem {
+    InstallationDetailItem {
+        name: tool.name.clone(),
+        description: tool.description.clone(),
+        os: installation.os,
+        arch: installation.arch,
+        id: installation.id.clone(),
+        r#type: installation.r#type,
+        source: installation.source.clone(),
+        script: installation.script.clone(),
+        pre_install: installation.pre_install.clone(),
+        post_install: installation.post_install.clone(),
+        interactive: installation.interactive,
+        depends_on: tool.depends_on.clone(),
+        provides: tool.provides.clone(),
+        fallbacks: vec![],
+    }
+}
+
 #[cfg(test)]
 mod test_get_tookits_manifest {
     use super::*;
@@ -69,11 +288,92 @@ mod test_get_tookits_manifest {
 
     #[tokio::test]
     async fn test_with_remote_file() -> Result<()> {
-        let toolkits_manifest = get_tookits_manifest(
-            "https://raw.githubusercontent.com/apptools-lab/AppToolkit/feat/cli/fixtures/toolkits.manifest.json",
+        // the fixture is not signed
+        let options = ManifestOptions {
+            insecure_skip_verify: true,
+            ..Default::default()
+        };
+        let toolkits_manifest = resolve_toolkits_manifest(
+            &[
+                "https://raw.githubusercontent.com/apptools-lab/AppToolkit/feat/cli/fixtures/toolkits.manifest.json"
+                    .to_string(),
+            ],
+            &options,
         )
         .await?;
         assert!(toolkits_manifest.toolkits.len() == 4);
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_read_stdin_once() {
+        // e.g. `--manifest - --manifest -`, once the first `-` was read
+        let err = load_composed_manifest("-", &ManifestOptions::default(), &mut vec![], &mut true)
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "The manifest `-` can only be read once from stdin");
+    }
+}
+
+#[cfg(test)]
+mod test_validate_installation_sources {
+    use super::*;
+
+    #[test]
+    fn test_require_source_or_script() -> Result<()> {
+        let tools: Vec<ToolInstallationManifest> = serde_json::from_value(serde_json::json!([{
+            "name": "fnm",
+            "installations": [
+                { "os": "macos", "type": "shell", "id": "fnm", "script": { "url": "https://fnm.vercel.app/install", "sha256": "" } },
+                { "os": "linux", "type": "shell", "id": "fnm" },
+                { "os": "windows", "type": "exe", "id": "fnm", "script": { "url": "https://fnm.vercel.app/install", "sha256": "" } }
+            ]
+        }]))?;
+        let err = validate_installation_sources(&tools).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "toolkits[0].installations[0]: `script.sha256` must be the 64 hex digits of a sha256 checksum\ntoolkits[0].installations[1]: missing `source`\ntoolkits[0].installations[2]: `script` is only supported by shell installations"
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test_filter_tool_installation_detail {
+    use super::*;
+
+    #[test]
+    fn test_select_one_installation_per_tool() -> Result<()> {
+        let os = env::consts::OS;
+        let arch = env::consts::ARCH;
+        let other_os = if os == "windows" { "linux" } else { "windows" };
+        let tools: Vec<ToolInstallationManifest> = serde_json::from_value(serde_json::json!([
+            {
+                "name": "Visual Studio Code",
+                "description": "",
+                "installations": [
+                    { "os": os, "type": "shell", "id": "code", "source": "arch-less" },
+                    { "os": os, "arch": arch, "type": "shell", "id": "code", "source": "arch-specific" }
+                ]
+            },
+            {
+                "name": "fnm",
+                "description": "",
+                "installations": [
+                    { "os": os, "type": "shell", "id": "fnm", "source": "script" },
+                    { "os": os, "type": "deb", "id": "fnm", "source": "deb", "priority": 10 },
+                    { "os": other_os, "type": "deb", "id": "fnm", "source": "other" }
+                ]
+            }
+        ]))?;
+
+        let tools_installation_detail = filter_tool_installation_detail(&tools)?;
+        assert_eq!(tools_installation_detail.len(), 2);
+        assert_eq!(tools_installation_detail[0].source, "arch-specific");
+        assert_eq!(tools_installation_detail[0].fallbacks[0].source, "arch-less");
+        assert_eq!(tools_installation_detail[1].source, "deb");
+        assert_eq!(tools_installation_detail[1].fallbacks.len(), 1);
+        assert_eq!(tools_installation_detail[1].fallbacks[0].source, "script");
+        Ok(())
+    }
 }
@@ -0,0 +1,268 @@
+use anyhow::Result;
+use std::{
+    fmt::{self, Display},
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{run_checked, CommandOptions, CommandRunner};
+
+/// A step which reverts a change of an installation.
+#[derive(Debug, Clone, PartialEq)]
+pub enum UndoAction {
+    /// Removes a file or directory created by the installation.
+    RemovePath(PathBuf),
+    /// Removes a file written by an installer, unless it was modified since, e.g. the command installed by a shell
+    /// script.
+    RemoveInstalledFile { path: PathBuf, modified: SystemTime },
+    /// Moves back a file or directory which was replaced by the installation.
+    RestorePath { path: PathBuf, backup: PathBuf },
+    /// Runs a command which reverts an installation step, e.g. the uninstaller of an exe installation.
+    RunCommand(String),
+}
+
+impl Display for UndoAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UndoAction::RemovePath(path) | UndoAction::RemoveInstalledFile { path, .. } => {
+                write!(f, "remove '{}'", path.display())
+            }
+            UndoAction::RestorePath { path, .. } => write!(f, "restore '{}'", path.display()),
+            UndoAction::RunCommand(command) => write!(f, "run `{}`", command),
+        }
+    }
+}
+
+impl UndoAction {
+    async fn undo(&self, runner: &dyn CommandRunner, options: &CommandOptions) -> Result<()> {
+        match self {
+            UndoAction::RemovePath(path) => remove_path(path),
+            UndoAction::RemoveInstalledFile { path, modified } => {
+                // the file is only removed while it is still the one the installer wrote
+                if fs::symlink_metadata(path)?.modified()? != *modified {
+                    return Err(anyhow::anyhow!(
+                        "'{}' was modified since it was installed",
+                        path.display()
+                    ));
+                }
+                fs::remove_file(path)?;
+                Ok(())
+            }
+            UndoAction::RestorePath { path, backup } => {
+                remove_path(path)?;
+                fs::rename(backup, path)?;
+                Ok(())
+            }
+            UndoAction::RunCommand(command) => run_checked(runner, command, options).await.map(|_| ()),
+        }
+    }
+}
+
+/// The undo actions registered by the steps of an installation, reverted in reverse order when a later step fails.
+#[derive(Debug, Default)]
+pub struct Transaction {
+    actions: Vec<UndoAction>,
+}
+
+impl Transaction {
+    pub fn register(&mut self, action: UndoAction) {
+        self.actions.push(action);
+    }
+
+    /// Prepares a path which the installation is going to write: an existing file or directory is moved aside to be
+    /// restored on rollback, otherwise the path is removed on rollback. Fails when a backup of the path is left by an
+    /// earlier installation, as it may be the only copy of the original.
+    pub fn prepare_path(&mut self, path: &Path) -> Result<()> {
+        if fs::symlink_metadata(path).is_err() {
+            self.register(UndoAction::RemovePath(path.to_path_buf()));
+            return Ok(());
+        }
+        let mut backup = path.as_os_str().to_owned();
+        backup.push(".toolkit-backup");
+        let backup = PathBuf::from(backup);
+        if fs::symlink_metadata(&backup).is_ok() {
+            return Err(anyhow::anyhow!(
+                "The backup '{}' of an earlier installation exists. Restore or remove it before installing '{}' again",
+                backup.display(),
+                path.display()
+            ));
+        }
+        fs::rename(path, &backup)
+            .map_err(|err| anyhow::anyhow!("Failed to move '{}' aside. Error: {}", path.display(), err))?;
+        self.register(UndoAction::RestorePath {
+            path: path.to_path_buf(),
+            backup,
+        });
+        Ok(())
+    }
+
+    /// Reverts the registered actions in reverse order, running the commands with the runner. Every action is
+    /// attempted, and the actions which failed are returned as the error.
+    pub async fn rollback(
+        &mut self,
+        runner: &dyn CommandRunner,
+        options: &CommandOptions,
+        log: impl Fn(&str),
+    ) -> Result<()> {
+        let mut failures = vec![];
+        while let Some(action) = self.actions.pop() {
+            match action.undo(runner, options).await {
+                Ok(()) => log(&format!("Rolled back: {}", action)),
+                Err(err) => failures.push(format!("{}: {}", action, err)),
+            }
+        }
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!("Failed to roll back: {}", failures.join("; ")))
+        }
+    }
+
+    /// Keeps the changes of the installation and removes the backups of the replaced paths.
+    pub fn commit(&mut self) {
+        for action in self.actions.drain(..) {
+            if let UndoAction::RestorePath { backup, .. } = action {
+                let _ = remove_path(&backup);
+            }
+        }
+    }
+
+    /// Drops the registered actions without reverting them, e.g. with `--no-rollback`.
+    pub fn discard(&mut self) -> Vec<UndoAction> {
+        self.actions.drain(..).collect()
+    }
+}
+
+fn remove_path(path: &Path) -> Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path)?,
+        Ok(_) => fs::remove_file(path)?,
+        Err(_) => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test_transaction {
+    use super::*;
+    use crate::{FakeCommandRunner, FakeOutput};
+    use std::{
+        env,
+        time::{SystemTime, UNIX_EPOCH},
+    };
+
+    fn create_temp_dir() -> PathBuf {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+        let dir = env::temp_dir().join(format!("toolkit-test-transaction-{}", nanos));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn test_rollback() -> Result<()> {
+        let dir = create_temp_dir();
+        let app_path = dir.join("Visual Studio Code.app");
+        let config_path = dir.join("settings.json");
+        fs::write(&config_path, "old")?;
+
+        let mut transaction = Transaction::default();
+        transaction.prepare_path(&app_path)?;
+        fs::create_dir_all(app_path.join("Contents"))?;
+        transaction.prepare_path(&config_path)?;
+        fs::write(&config_path, "new")?;
+        transaction.register(UndoAction::RunCommand("unins000.exe /SILENT".to_string()));
+        transaction.register(UndoAction::RunCommand("fnm uninstall 22".to_string()));
+
+        let runner = FakeCommandRunner::default().with_output("fnm", FakeOutput::failure(1, "fnm: not found"));
+        let logs = std::cell::RefCell::new(vec![]);
+        let err = transaction
+            .rollback(&runner, &CommandOptions::default(), |msg| {
+                logs.borrow_mut().push(msg.to_string())
+            })
+            .await
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("Failed to roll back: run `fnm uninstall 22`: `fnm uninstall 22` failed with"));
+        assert!(!app_path.exists());
+        assert_eq!(fs::read_to_string(&config_path)?, "old");
+        assert_eq!(runner.commands(), vec!["fnm uninstall 22", "unins000.exe /SILENT"]);
+        assert_eq!(
+            logs.into_inner(),
+            vec![
+                "Rolled back: run `unins000.exe /SILENT`".to_string(),
+                format!("Rolled back: restore '{}'", config_path.display()),
+                format!("Rolled back: remove '{}'", app_path.display()),
+            ]
+        );
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_commit() -> Result<()> {
+        let dir = create_temp_dir();
+        let config_path = dir.join("settings.json");
+        fs::write(&config_path, "old")?;
+
+        let mut transaction = Transaction::default();
+        transaction.prepare_path(&config_path)?;
+        fs::write(&config_path, "new")?;
+        transaction.commit();
+
+        assert_eq!(fs::read_to_string(&config_path)?, "new");
+        assert_eq!(fs::read_dir(&dir)?.count(), 1);
+        assert!(transaction.discard().is_empty());
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_keep_existing_backup() -> Result<()> {
+        let dir = create_temp_dir();
+        let config_path = dir.join("settings.json");
+        let backup_path = dir.join("settings.json.toolkit-backup");
+        fs::write(&config_path, "new")?;
+        // left by an interrupted installation
+        fs::write(&backup_path, "old")?;
+
+        let err = Transaction::default().prepare_path(&config_path).unwrap_err();
+        assert!(err.to_string().contains(&backup_path.display().to_string()), "{}", err);
+        assert_eq!(fs::read_to_string(&backup_path)?, "old");
+        assert_eq!(fs::read_to_string(&config_path)?, "new");
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_keep_modified_installed_file() -> Result<()> {
+        let dir = create_temp_dir();
+        let bin_path = dir.join("fnm");
+        fs::write(&bin_path, "#!/bin/sh\n")?;
+        let modified = fs::metadata(&bin_path)?.modified()? - std::time::Duration::from_secs(60);
+
+        let mut transaction = Transaction::default();
+        transaction.register(UndoAction::RemoveInstalledFile {
+            path: bin_path.clone(),
+            modified,
+        });
+        let runner = FakeCommandRunner::default();
+        let err = transaction
+            .rollback(&runner, &CommandOptions::default(), |_| {})
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string().ends_with("was modified since it was installed"),
+            "{}",
+            err
+        );
+        assert!(bin_path.exists());
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -3,95 +3,79 @@ pub mod windows_installation {
     extern crate winreg;
 
     use anyhow::Result;
-    use console::style;
-    use indicatif::{MultiProgress, ProgressBar};
-    use std::{
-        collections::HashSet,
-        sync::{Arc, Mutex},
-        time::Duration,
-    };
+    use std::{collections::HashSet, path::Path};
     use winreg::{enums::*, RegKey, HKEY};
 
     use crate::{
-        download_file, installation::handle_installation_finish_message, run_command_on_windows, InstallStatus,
-        InstallationDetailItem, Type, SPINNER_STYLE,
+        run_command_steps, CommandOptions, CommandRunner, CommandSteps, InstallContext, InstallStatus,
+        InstallationDetailItem, RetriableError, Type, UndoAction,
     };
 
-    pub async fn install(tools_installation_detail: Vec<InstallationDetailItem>) -> Result<()> {
-        let multi_progress = MultiProgress::new();
-        let tools_count = tools_installation_detail.len();
-
-        let installation_results: Arc<Mutex<Vec<String>>> = Arc::new(Mutex::new(Vec::with_capacity(tools_count)));
-
-        let installed_app_display_names = get_installed_app_display_names()?;
-
-        let handles = tools_installation_detail
-            .into_iter()
-            .enumerate()
-            .map(|(index, tool_installation_info)| {
-                let pb = multi_progress.add(ProgressBar::new(100));
-                pb.set_style(SPINNER_STYLE.clone());
-                pb.set_prefix(format!("[{}/{}]", index + 1, tools_count));
-                pb.enable_steady_tick(Duration::from_millis(120));
-
-                let installation_results = Arc::clone(&installation_results);
-                let installed_app_display_names = installed_app_display_names.clone();
-
-                tokio::spawn(async move {
-                    let mut installation_result: Option<Result<InstallStatus, anyhow::Error>> = None;
-
-                    match tool_installation_info.r#type {
-                        Type::Exe => {
-                            let exe_installation_result = install_tool_by_exe(
-                                &tool_installation_info.id,
-                                &tool_installation_info.source,
-                                tool_installation_info.post_install.as_deref(),
-                                &installed_app_display_names,
-                                |msg| {
-                                    pb.set_message(format!("{}: {}", style(&tool_installation_info.name).bold(), msg))
-                                },
-                            )
-                            .await;
-                            installation_result = Some(exe_installation_result);
-                        }
-                        _ => {
-                            let errror_message = format!(
-                                "Unsupported installation type: {}. App: {}",
-                                style(&tool_installation_info.r#type).bold(),
-                                style(&tool_installation_info.name).bold(),
-                            );
-                            pb.finish_with_message(errror_message.clone());
-                            installation_results.lock().unwrap().push(errror_message);
-                        }
-                    }
-                    if let Some(installation_result) = installation_result {
-                        handle_installation_finish_message(
-                            &pb,
-                            &tool_installation_info.name,
-                            installation_result,
-                            &mut installation_results.lock().unwrap(),
-                        );
-                    }
+    pub const SUPPORTED_TYPES: &[Type] = &[Type::Exe];
+
+    pub async fn install_tool(
+        tool_installation_detail: &InstallationDetailItem,
+        ctx: &InstallContext,
+    ) -> Result<InstallStatus> {
+        match tool_installation_detail.r#type {
+            Type::Exe => {
+                let installed_app_display_names = get_installed_app_display_names()?;
+                install_tool_by_exe(
+                    &tool_installation_detail.id,
+                    &tool_installation_detail.source,
+                    tool_installation_detail.post_install.as_ref(),
+                    &installed_app_display_names,
+                    ctx,
+                    |msg| ctx.set_message(msg),
+                )
+                .await
+            }
+            _ => Err(anyhow::anyhow!(
+                "Unsupported installation type: {}",
+                tool_installation_detail.r#type
+            )),
+        }
+    }
+
+    pub async fn is_tool_installed(
+        tool_installation_detail: &InstallationDetailItem,
+        _runner: &dyn CommandRunner,
+    ) -> bool {
+        match tool_installation_detail.r#type {
+            Type::Exe => get_installed_app_display_names()
+                .map(|installed_app_display_names| {
+                    is_app_installed(&tool_installation_detail.id, &installed_app_display_names)
                 })
-            });
-
-        futures::future::join_all(handles).await;
-        // clear the progress bar
-        multi_progress.clear().expect("failed to clear progress bar");
-        // print the installation results
-        let installation_results = installation_results.lock().unwrap();
-        for result in installation_results.iter() {
-            println!("{}", result);
+                .unwrap_or(false),
+            _ => false,
         }
+    }
 
-        Ok(())
+    /// Reads the `DisplayVersion` of the app from the uninstall registry keys.
+    pub async fn detect_app_version(
+        tool_installation_detail: &InstallationDetailItem,
+        _runner: &dyn CommandRunner,
+    ) -> Option<String> {
+        get_uninstall_value(&tool_installation_detail.id, "DisplayVersion")
     }
 
-    #[cfg(target_os = "windows")]
-    pub fn get_installed_app_display_names() -> Result<HashSet<String>> {
-        let mut display_names_set: HashSet<String> = HashSet::new();
+    /// Reads a value of the uninstall registry key of the app with the display name.
+    fn get_uninstall_value(display_name: &str, name: &str) -> Option<String> {
+        get_uninstall_paths().into_iter().find_map(|(hkey, path)| {
+            let uninstall = RegKey::predef(hkey).open_subkey_with_flags(path, KEY_READ).ok()?;
+            uninstall.enum_keys().filter_map(|key| key.ok()).find_map(|key| {
+                let key = uninstall.open_subkey_with_flags(key, KEY_READ).ok()?;
+                if key.get_value::<String, _>("DisplayName").ok()? == display_name {
+                    key.get_value::<String, _>(name).ok()
+                } else {
+                    None
+                }
+            })
+        })
+    }
 
-        let paths: Vec<(HKEY, &str)> = vec![
+    fn get_uninstall_paths() -> Vec<(HKEY, &'static str)> {
+        vec![
             (
                 HKEY_CURRENT_USER,
                 "Software\\Microsoft\\Windows\\CurrentVersion\\Uninstall",
@@ -104,9 +88,14 @@ pub mod windows_installation {
                 HKEY_LOCAL_MACHINE,
                 "Software\\Wow6432Node\\Microsoft\\Windows\\CurrentVersion\\Uninstall",
             ),
-        ];
+        ]
+    }
 
-        for path in paths {
+    #[cfg(target_os = "windows")]
+    pub fn get_installed_app_display_names() -> Result<HashSet<String>> {
+        let mut display_names_set: HashSet<String> = HashSet::new();
+
+        for path in get_uninstall_paths() {
             let display_names = get_app_display_names(path);
             for display_name in display_names {
                 if !display_names_set.contains(&display_name) {
@@ -147,35 +136,85 @@ pub mod windows_installation {
     async fn install_tool_by_exe(
         id: &str,
         source: &str,
-        post_install: Option<&str>,
+        post_install: Option<&CommandSteps>,
         installed_app_display_names: &HashSet<String>,
+        ctx: &InstallContext,
         set_process_message: impl Fn(&str),
     ) -> Result<InstallStatus> {
         if is_app_installed(id, installed_app_display_names) {
-            return Ok(InstallStatus::AlreadyInstalled);
+            Ok(InstallStatus::AlreadyInstalled)
         } else {
             set_process_message("Downloading...");
-            let exe_path = download_file(source, &set_process_message).await?;
+            let exe_path = ctx.download(source).await?;
 
             set_process_message("Installing...");
-            let output = run_command_on_windows(&exe_path.to_string_lossy())?;
-            if !output.status.success() {
-                return Err(anyhow::anyhow!(
-                    "Installation failed with output: {}",
-                    String::from_utf8_lossy(&output.stderr)
-                ));
+            let result = run_exe(&exe_path, ctx).await;
+            // the installer is only needed again to retry the download
+            let _ = std::fs::remove_file(&exe_path);
+            result?;
+            // the app is uninstalled when a later step fails, without prompts when the installer registered a quiet
+            // uninstaller
+            let uninstall =
+                get_uninstall_value(id, "QuietUninstallString").or_else(|| get_uninstall_value(id, "UninstallString"));
+            if let Some(uninstall) = uninstall {
+                ctx.register_undo(UndoAction::RunCommand(uninstall));
             }
 
             if let Some(post_install) = post_install {
-                set_process_message("Running post-install script...");
-                run_command_on_windows(post_install)?;
+                run_command_steps("post-install", post_install, ctx).await?;
             }
 
             Ok(InstallStatus::Installed)
         }
     }
 
+    /// Runs the installer with PowerShell, whose quoting is kept intact by the command line of the process unlike the
+    /// one of `cmd`, so that a path with a space, e.g. in the user name of the temporary directory, is one argument.
+    async fn run_exe(exe_path: &Path, ctx: &InstallContext) -> Result<()> {
+        let options = CommandOptions {
+            shell: Some("powershell".to_string()),
+            timeout: ctx.timeout(),
+            ..Default::default()
+        };
+        let program = format!(
+            "& {}; exit $LASTEXITCODE",
+            quote_powershell_arg(&exe_path.to_string_lossy())
+        );
+        if ctx.is_interactive() {
+            return ctx
+                .run_interactive(&program, &options)
+                .await
+                .map_err(RetriableError::wrap);
+        }
+        let output = ctx.runner().run(&program, &options).await?;
+        if !output.status.success() {
+            return Err(RetriableError::wrap(anyhow::anyhow!(
+                "Installation failed with output: {}",
+                String::from_utf8_lossy(&output.stderr)
+            )));
+        }
+        Ok(())
+    }
+
+    pub(super) fn quote_powershell_arg(arg: &str) -> String {
+        format!("'{}'", arg.replace('\'', "''"))
+    }
+
     fn is_app_installed(display_name: &str, installed_app_display_names: &HashSet<String>) -> bool {
         installed_app_display_names.contains(display_name)
     }
 }
+
+#[cfg(target_os = "windows")]
+#[cfg(test)]
+mod test_install_fn_on_windows {
+    use super::windows_installation::*;
+
+    #[test]
+    fn test_quote_powershell_arg() {
+        assert_eq!(
+            quote_powershell_arg(r"C:\Users\John O'Neil\AppData\Local\Temp\setup.exe"),
+            r"'C:\Users\John O''Neil\AppData\Local\Temp\setup.exe'"
+        );
+    }
+}
@@ -1,7 +1,9 @@
 mod cli;
+mod config;
 mod installation;
 mod utils;
 
 pub use cli::*;
+pub use config::*;
 pub use installation::*;
 pub use utils::*;
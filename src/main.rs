@@ -1,20 +1,117 @@
-use clap::Parser;
-use toolkit::{install, Cli, Commands};
+use clap::{CommandFactory, FromArgMatches};
+use std::process::ExitCode;
+use toolkit::{
+    apply_project_manifest, format_config_value, get_config_value, get_tool_script, install, list_config_values,
+    resolve_toolkits_manifest, set_config_value, Cli, Commands, Config, ConfigCommands, ExitStatus, InstallOpts,
+    ManifestCommands, ManifestError, ManifestOptions, ManifestResolveOpts, ManifestScriptOpts,
+};
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    let cli = Cli::parse();
+fn main() -> ExitCode {
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("Error: {:?}", err);
+            return ExitStatus::ManifestError.into();
+        }
+    };
+    // the environment is only changed while toolkit has a single thread, before the runtime starts its workers
+    config.apply_proxy();
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("failed to start the tokio runtime");
+    runtime.block_on(run(config))
+}
+
+async fn run(config: Config) -> ExitCode {
+    let matches = config.apply_defaults(Cli::command()).get_matches();
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    if let Err(err) = apply_project_manifest(&mut cli, &matches) {
+        eprintln!("Error: {:?}", err);
+        return ExitStatus::Usage.into();
+    }
 
-    match cli.command {
+    let exit_status = match cli.command {
         Some(command) => match command {
-            Commands::Install(opts) => {
-                install(&opts.manifest).await?;
-            }
+            Commands::Install(opts) => run_install(&opts).await,
+            Commands::Manifest(opts) => match opts.command {
+                ManifestCommands::Resolve(opts) => run_manifest_resolve(&opts).await,
+                ManifestCommands::Script(opts) => run_manifest_script(&opts).await,
+            },
+            Commands::Config(opts) => run_config(opts.command),
         },
         None => {
             eprintln!("No command provided");
+            ExitStatus::Usage
+        }
+    };
+
+    exit_status.into()
+}
+
+async fn run_install(opts: &InstallOpts) -> ExitStatus {
+    match install(opts).await {
+        Ok(report) => ExitStatus::from_report(&report),
+        Err(err) => {
+            eprintln!("Error: {:?}", err);
+            ExitStatus::from_error(&err)
         }
     }
+}
 
-    Ok(())
+async fn run_manifest_resolve(opts: &ManifestResolveOpts) -> ExitStatus {
+    let result = async {
+        let manifest_options = ManifestOptions::new(opts.source.offline, opts.source.insecure_skip_verify)?;
+        resolve_toolkits_manifest(&opts.source.manifest, &manifest_options).await
+    }
+    .await
+    .map_err(|err| anyhow::Error::new(ManifestError::new(&opts.source.manifest.join(", "), err)))
+    .and_then(|manifest| Ok(serde_json::to_string_pretty(&manifest)?));
+    match result {
+        Ok(json) => {
+            println!("{}", json);
+            ExitStatus::Success
+        }
+        Err(err) => {
+            eprintln!("Error: {:?}", err);
+            ExitStatus::from_error(&err)
+        }
+    }
+}
+
+async fn run_manifest_script(opts: &ManifestScriptOpts) -> ExitStatus {
+    match get_tool_script(opts).await {
+        Ok(script) => {
+            print!("{}", script);
+            ExitStatus::Success
+        }
+        Err(err) => {
+            eprintln!("Error: {:?}", err);
+            ExitStatus::from_error(&err)
+        }
+    }
+}
+
+fn run_config(command: ConfigCommands) -> ExitStatus {
+    let result = match command {
+        ConfigCommands::Get { key } => get_config_value(&key).map(|(value, _)| {
+            if let Some(value) = value {
+                println!("{}", format_config_value(&value));
+            }
+        }),
+        ConfigCommands::Set { key, value } => set_config_value(&key, &value),
+        ConfigCommands::List => list_config_values().map(|values| {
+            for (key, value, source) in values {
+                let value = value.as_ref().map(format_config_value).unwrap_or_default();
+                println!("{} = {} ({})", key.name, value, source);
+            }
+        }),
+    };
+    match result {
+        Ok(()) => ExitStatus::Success,
+        Err(err) => {
+            eprintln!("Error: {:?}", err);
+            ExitStatus::Usage
+        }
+    }
 }
@@ -1,115 +1,515 @@
-use std::io::{BufRead, BufReader};
-use std::process::Output;
+use std::process::{ExitStatus, Output};
 use std::{
+    collections::{BTreeMap, VecDeque},
     env,
-    path::Path,
-    process::{Command, Stdio},
+    error::Error,
+    fmt::{self, Display},
+    io,
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, BufReader, Lines},
+    process::{Child, Command},
 };
 
-pub fn run_command_pipe_on_unix(program: &str, set_process_message: impl Fn(&str)) -> anyhow::Result<()> {
-    let shell = get_default_shell();
-    let unix_shell_config_path = get_unix_shell_config_path(&shell);
-    let mut command = if Path::new(&unix_shell_config_path).exists() {
-        format!("source {}", &unix_shell_config_path)
-    } else {
-        "".to_string()
-    };
-    command = format!("{} && {}", command, program);
+use super::command_runner::{run_checked, SystemCommandRunner};
 
-    let mut child = Command::new(&shell)
-        .args(["-c", &command])
+/// The options of a command run by [`run_shell_command`] or [`run_command_streaming`].
+#[derive(Debug, Clone, Default)]
+pub struct CommandOptions {
+    /// The environment variables added to the environment of toolkit.
+    pub env: BTreeMap<String, String>,
+    pub cwd: Option<PathBuf>,
+    /// The shell which runs the command, e.g. `bash` or `pwsh`. Defaults to the user's shell with its rc file on unix
+    /// and `cmd` on windows.
+    pub shell: Option<String>,
+    pub timeout: Option<Duration>,
+}
+
+/// The error of a command which was killed because it did not finish in time.
+#[derive(Debug)]
+pub struct CommandTimeoutError {
+    pub program: String,
+    pub timeout: Duration,
+}
+
+impl Display for CommandTimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}` timed out after {}s", self.program, self.timeout.as_secs())
+    }
+}
+
+impl Error for CommandTimeoutError {}
+
+impl CommandTimeoutError {
+    pub(super) fn error(program: &str, timeout: Duration) -> anyhow::Error {
+        anyhow::Error::new(Self {
+            program: program.to_string(),
+            timeout,
+        })
+    }
+}
+
+/// Runs the command in a shell and fails when it exits with a non-zero code, with its stderr in the error.
+pub async fn run_shell_command(program: &str, options: &CommandOptions) -> anyhow::Result<Output> {
+    run_checked(&SystemCommandRunner, program, options).await
+}
+
+pub(super) fn ensure_success(program: &str, output: Output) -> anyhow::Result<Output> {
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "`{}` failed with {}: {}",
+            program,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(output)
+}
+
+/// The number of the last output lines included in the error of a failed command.
+const ERROR_OUTPUT_LINES: usize = 20;
+
+/// Runs the command in a shell and passes every line of its stdout and stderr to `on_line` as it is printed, the
+/// stderr lines prefixed with `[stderr]`. Fails when the command exits with a non-zero code, with the last lines of its
+/// output in the error. The command and the processes it started are killed when it times out or when the returned
+/// future is dropped, e.g. when the installation is cancelled.
+pub async fn run_command_streaming(
+    program: &str,
+    options: &CommandOptions,
+    mut on_line: impl FnMut(&str),
+) -> anyhow::Result<()> {
+    let (shell, mut command) = build_command(program, options);
+    let mut child = command
         .stdout(Stdio::piped())
-        .spawn()?;
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| anyhow::anyhow!("Failed to run `{}` with {}. Error: {}", program, shell, err))?;
+    let mut process_group = ProcessGroupGuard::new(&child);
 
-    if let Some(ref mut stdout) = child.stdout {
-        let reader = BufReader::new(stdout);
-        for line in reader.lines() {
-            set_process_message(line?.as_str());
+    let mut last_lines = VecDeque::with_capacity(ERROR_OUTPUT_LINES);
+    let run = async {
+        let mut stdout = child.stdout.take().map(|stdout| BufReader::new(stdout).lines());
+        let mut stderr = child.stderr.take().map(|stderr| BufReader::new(stderr).lines());
+        // both streams are read at the same time, so a command which fills the stderr pipe while nothing is printed to
+        // stdout does not block
+        while stdout.is_some() || stderr.is_some() {
+            let (is_stderr, line) = tokio::select! {
+                line = next_line(&mut stdout) => (false, line?),
+                line = next_line(&mut stderr) => (true, line?),
+            };
+            let line = match (is_stderr, line) {
+                (false, Some(line)) => line,
+                (true, Some(line)) => format!("[stderr] {}", line),
+                (false, None) => {
+                    stdout = None;
+                    continue;
+                }
+                (true, None) => {
+                    stderr = None;
+                    continue;
+                }
+            };
+            on_line(&line);
+            if last_lines.len() == ERROR_OUTPUT_LINES {
+                last_lines.pop_front();
+            }
+            last_lines.push_back(line);
         }
-    } else if let Some(ref mut stderr) = child.stderr {
-        let reader = BufReader::new(stderr);
-        let error = reader.lines().collect::<Result<Vec<_>, _>>()?.join("\n");
-        return Err(anyhow::anyhow!(error));
+        child.wait().await
+    };
+    let status = match options.timeout {
+        Some(timeout) => tokio::time::timeout(timeout, run)
+            .await
+            .map_err(|_| CommandTimeoutError::error(program, timeout))?,
+        None => run.await,
+    }
+    .map_err(|err| anyhow::anyhow!("Failed to read the output of `{}`. Error: {}", program, err))?;
+    process_group.disarm();
+    if !status.success() {
+        return Err(failed_with_output(program, status, last_lines));
     }
     Ok(())
 }
 
-pub fn run_command_on_unix(program: &str) -> anyhow::Result<Output> {
+pub(super) fn failed_with_output(
+    program: &str,
+    status: ExitStatus,
+    last_lines: impl IntoIterator<Item = String>,
+) -> anyhow::Error {
+    let output = last_lines.into_iter().collect::<Vec<_>>().join("\n");
+    anyhow::anyhow!("`{}` failed with {}. Output:\n{}", program, status, output)
+}
+
+async fn next_line<R: AsyncBufRead + Unpin>(lines: &mut Option<Lines<R>>) -> io::Result<Option<String>> {
+    match lines {
+        Some(lines) => lines.next_line().await,
+        // a closed stream never yields again, so the other stream is selected
+        None => std::future::pending().await,
+    }
+}
+
+pub(super) fn build_command(program: &str, options: &CommandOptions) -> (String, Command) {
+    let (shell, args) = get_shell_invocation(program, options.shell.as_deref());
+    let mut command = std::process::Command::new(&shell);
+    command.args(&args).envs(&options.env).stdin(Stdio::null());
+    // the command leads its own process group, so that the processes it starts are killed with it
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+    if let Some(cwd) = &options.cwd {
+        command.current_dir(cwd);
+    }
+    let mut command = Command::from(command);
+    command.kill_on_drop(true);
+    (shell, command)
+}
+
+/// Kills the process tree of a command when it is dropped before the command exits, e.g. on timeout or when the
+/// installation is cancelled. `kill_on_drop` alone only kills the shell and leaves its children running.
+pub(super) struct ProcessGroupGuard {
+    pid: Option<u32>,
+}
+
+impl ProcessGroupGuard {
+    pub(super) fn new(child: &Child) -> Self {
+        Self::from_pid(child.id())
+    }
+
+    /// Guards a process which leads its own process group, e.g. a command started in a pseudo-terminal.
+    pub(super) fn from_pid(pid: Option<u32>) -> Self {
+        Self { pid }
+    }
+
+    /// Keeps the processes running once the command exited by itself.
+    pub(super) fn disarm(&mut self) {
+        self.pid = None;
+    }
+}
+
+impl Drop for ProcessGroupGuard {
+    fn drop(&mut self) {
+        if let Some(pid) = self.pid {
+            kill_process_tree(pid);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn kill_process_tree(pid: u32) {
+    // SAFETY: killpg only sends a signal, the process group is the one created for the command in `build_command`
+    // or by the pseudo-terminal
+    unsafe {
+        libc::killpg(pid as libc::pid_t, libc::SIGKILL);
+    }
+}
+
+#[cfg(windows)]
+fn kill_process_tree(pid: u32) {
+    let _ = std::process::Command::new("taskkill")
+        .args(["/F", "/T", "/PID", &pid.to_string()])
+        .output();
+}
+
+/// The kind of a shell, detected by the file name of its path so that `/usr/bin/zsh` or a Nix store path is
+/// recognized as well as `/bin/zsh`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ShellKind {
+    Posix,
+    Bash,
+    Zsh,
+    Fish,
+    Cmd,
+    PowerShell,
+}
+
+impl ShellKind {
+    fn detect(shell: &str) -> Self {
+        let name = Path::new(shell)
+            .file_stem()
+            .map(|name| name.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        match name.as_str() {
+            "bash" => ShellKind::Bash,
+            "zsh" => ShellKind::Zsh,
+            "fish" => ShellKind::Fish,
+            "cmd" => ShellKind::Cmd,
+            "powershell" | "pwsh" => ShellKind::PowerShell,
+            _ => ShellKind::Posix,
+        }
+    }
+
+    /// The rc file which sets up the `PATH` of the shell, e.g. for the tools installed by a version manager.
+    fn rc_file(self, home: &Path) -> Option<PathBuf> {
+        match self {
+            ShellKind::Bash => Some(home.join(".bashrc")),
+            ShellKind::Zsh => Some(home.join(".zshrc")),
+            ShellKind::Fish => Some(home.join(".config/fish/config.fish")),
+            ShellKind::Posix => Some(home.join(".profile")),
+            ShellKind::Cmd | ShellKind::PowerShell => None,
+        }
+    }
+
+    fn quote(self, arg: &str) -> String {
+        match self {
+            // backslashes are escapes in the single-quoted strings of fish
+            ShellKind::Fish => format!("'{}'", arg.replace('\\', r"\\").replace('\'', r"\'")),
+            _ => quote_shell_arg(arg),
+        }
+    }
+
+    /// Returns the arguments which make the shell run the program, after loading the rc file when there is one. The
+    /// program runs whatever the exit code of the rc file, which is often the one of a `[ -s file ] && . file`.
+    fn args(self, program: &str, rc_file: Option<&Path>) -> Vec<String> {
+        let (flags, program): (&[&str], String) = match (self, rc_file) {
+            (ShellKind::Cmd, _) => (&["/C"], program.to_string()),
+            (ShellKind::PowerShell, _) => (&["-NoProfile", "-Command"], program.to_string()),
+            (_, None) => (&["-c"], program.to_string()),
+            (ShellKind::Fish, Some(rc_file)) => (
+                &["-c"],
+                format!("source {}; {}", self.quote(&rc_file.to_string_lossy()), program),
+            ),
+            (ShellKind::Posix, Some(rc_file)) => (
+                &["-c"],
+                format!(". {}; {}", self.quote(&rc_file.to_string_lossy()), program),
+            ),
+            (_, Some(rc_file)) => (
+                &["-c"],
+                format!("source {}; {}", self.quote(&rc_file.to_string_lossy()), program),
+            ),
+        };
+        flags.iter().map(|flag| flag.to_string()).chain([program]).collect()
+    }
+}
+
+/// Returns the shell and its arguments which run the program. An explicit shell runs the program as is, otherwise the
+/// user's shell loads its rc file first on unix, and `cmd` is used on windows.
+pub(super) fn get_shell_invocation(program: &str, shell: Option<&str>) -> (String, Vec<String>) {
+    if let Some(shell) = shell {
+        return (shell.to_string(), ShellKind::detect(shell).args(program, None));
+    }
+    if cfg!(windows) {
+        return ("cmd".to_string(), ShellKind::Cmd.args(program, None));
+    }
     let shell = get_default_shell();
-    let unix_shell_config_path = get_unix_shell_config_path(&shell);
-    let mut command = if Path::new(&unix_shell_config_path).exists() {
-        format!("source {}", &unix_shell_config_path)
-    } else {
-        "".to_string()
-    };
-    command = format!("{} && {}", command, program);
-    let output = Command::new(&shell)
-        .args(["-c", &command])
+    let kind = ShellKind::detect(&shell);
+    let rc_file = home::home_dir()
+        .filter(|home| !home.as_os_str().is_empty())
+        .and_then(|home| kind.rc_file(&home))
+        .filter(|rc_file| rc_file.is_file());
+    let args = kind.args(program, rc_file.as_deref());
+    (shell, args)
+}
+
+/// Runs the command in the user's shell and passes every line of its output to `set_process_message`.
+pub async fn run_command_pipe_on_unix(program: &str, set_process_message: impl Fn(&str)) -> anyhow::Result<()> {
+    run_command_streaming(program, &CommandOptions::default(), |line| set_process_message(line)).await
+}
+
+pub async fn run_command_on_unix(program: &str) -> anyhow::Result<Output> {
+    let (shell, mut command) = build_command(program, &CommandOptions::default());
+    let output = command
         .output()
-        .expect("failed to execute process");
+        .await
+        .map_err(|err| anyhow::anyhow!("Failed to run `{}` with {}. Error: {}", program, shell, err))?;
     Ok(output)
 }
 
-pub fn run_command_on_windows<T: AsRef<str>>(program: T) -> anyhow::Result<Output> {
-    let output = Command::new("cmd")
-        .args(["/C", program.as_ref()])
+pub async fn run_command_on_windows<T: AsRef<str>>(program: T) -> anyhow::Result<Output> {
+    let options = CommandOptions {
+        shell: Some("cmd".to_string()),
+        ..Default::default()
+    };
+    let (_, mut command) = build_command(program.as_ref(), &options);
+    let output = command
         .output()
-        .expect("failed to execute process");
+        .await
+        .map_err(|err| anyhow::anyhow!("Failed to run `{}` with cmd. Error: {}", program.as_ref(), err))?;
     Ok(output)
 }
 
-pub fn is_cmd_exists<T: AsRef<str>>(program: T) -> anyhow::Result<bool> {
+pub async fn is_cmd_exists<T: AsRef<str>>(program: T) -> anyhow::Result<bool> {
     if cfg!(windows) {
-        is_windows_cmd_exists(program)
+        is_windows_cmd_exists(program).await
     } else {
-        is_unix_cmd_exists(program)
+        is_unix_cmd_exists(program).await
     }
 }
 
-fn is_windows_cmd_exists<T: AsRef<str>>(program: T) -> anyhow::Result<bool> {
-    let output = Command::new("where").arg(program.as_ref()).output()?;
-    if output.status.success() {
-        Ok(true)
-    } else {
-        Ok(false)
-    }
+async fn is_windows_cmd_exists<T: AsRef<str>>(program: T) -> anyhow::Result<bool> {
+    let output = Command::new("where").arg(program.as_ref()).output().await?;
+    Ok(output.status.success())
 }
 
-fn is_unix_cmd_exists<T: AsRef<str>>(program: T) -> anyhow::Result<bool> {
-    let shell = get_default_shell();
-    let unix_shell_config_path = get_unix_shell_config_path(&shell);
-    let mut command = if Path::new(&unix_shell_config_path).exists() {
-        format!("source {}", &unix_shell_config_path)
-    } else {
-        "".to_string()
-    };
-    command = format!("{} && command -v {}", command, program.as_ref());
-
-    let output = Command::new(&shell).args(["-c", &command]).output()?;
+async fn is_unix_cmd_exists<T: AsRef<str>>(program: T) -> anyhow::Result<bool> {
+    let kind = ShellKind::detect(&get_default_shell());
+    let output = run_command_on_unix(&format!("command -v {}", kind.quote(program.as_ref()))).await?;
+    Ok(output.status.success())
+}
 
-    if output.status.success() {
-        Ok(true)
-    } else {
-        Ok(false)
+/// Quotes an argument for a POSIX shell command line.
+pub fn quote_shell_arg(arg: &str) -> String {
+    if !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:@%+,".contains(c))
+    {
+        return arg.to_string();
     }
+    format!("'{}'", arg.replace('\'', r"'\''"))
 }
 
 fn get_default_shell() -> String {
     match env::var("SHELL") {
-        Ok(shell) => shell,
-        Err(_) => "/bin/sh".to_string(),
+        Ok(shell) if !shell.is_empty() => shell,
+        _ => "/bin/sh".to_string(),
     }
 }
 
-fn get_unix_shell_config_path(shell: &str) -> String {
-    let home = match home::home_dir() {
-        Some(path) if !path.as_os_str().is_empty() => path.to_str().unwrap().to_string(),
-        _ => panic!("Unable to get your home dir!"),
-    };
-    match shell {
-        "/bin/zsh" => format!("{}/.zshrc", home).to_string(),
-        "/bin/bash" => format!("{}/.bashrc", home).to_string(),
-        "/bin/fish" => format!("{}/.config/fish/config.fish", home).to_string(),
-        _ => format!("{}/.bashrc", home).to_string(),
+#[cfg(test)]
+mod test_command {
+    use super::*;
+
+    #[test]
+    fn test_detect_shell() {
+        assert_eq!(ShellKind::detect("/usr/bin/zsh"), ShellKind::Zsh);
+        assert_eq!(
+            ShellKind::detect("/nix/store/0a1b-fish-3.7.1/bin/fish"),
+            ShellKind::Fish
+        );
+        assert_eq!(ShellKind::detect("/opt/homebrew/bin/bash"), ShellKind::Bash);
+        assert_eq!(ShellKind::detect("pwsh.exe"), ShellKind::PowerShell);
+        assert_eq!(ShellKind::detect("/bin/dash"), ShellKind::Posix);
+    }
+
+    #[test]
+    fn test_shell_args() {
+        let rc_file = Path::new("/home/it's me/.config/fish/config.fish");
+        assert_eq!(
+            ShellKind::Fish.args("fnm --version", Some(rc_file)),
+            vec![
+                "-c".to_string(),
+                r"source '/home/it\'s me/.config/fish/config.fish'; fnm --version".to_string()
+            ]
+        );
+        assert_eq!(
+            ShellKind::Zsh.args("fnm --version", Some(Path::new("/home/me/.zshrc"))),
+            vec!["-c".to_string(), "source /home/me/.zshrc; fnm --version".to_string()]
+        );
+        assert_eq!(
+            ShellKind::Posix.args("fnm --version", Some(Path::new("/home/me/.profile"))),
+            vec!["-c".to_string(), ". /home/me/.profile; fnm --version".to_string()]
+        );
+        assert_eq!(
+            ShellKind::Bash.args("fnm --version", None),
+            vec!["-c".to_string(), "fnm --version".to_string()]
+        );
+        assert_eq!(
+            ShellKind::Cmd.args("where fnm", None),
+            vec!["/C".to_string(), "where fnm".to_string()]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_program_after_failing_rc_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let rc_file = dir.path().join(".profile");
+        // the usual last line of an rc file, which fails when the file is missing
+        std::fs::write(
+            &rc_file,
+            "export TOOLKIT_RC=loaded\n[ -s \"$HOME/.missing\" ] && . \"$HOME/.missing\"\n",
+        )
+        .unwrap();
+        let output = std::process::Command::new("sh")
+            .args(ShellKind::Posix.args("echo $TOOLKIT_RC", Some(&rc_file)))
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "loaded\n");
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(ShellKind::Bash.quote("it's"), r"'it'\''s'");
+        assert_eq!(ShellKind::Fish.quote(r"C:\it's"), r"'C:\\it\'s'");
+        assert_eq!(ShellKind::Zsh.quote("fnm"), "fnm");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_run_command_streaming() {
+        let lines = std::cell::RefCell::new(vec![]);
+        let set_process_message = |line: &str| lines.borrow_mut().push(line.to_string());
+        run_command_pipe_on_unix("echo downloading; echo warning >&2", set_process_message)
+            .await
+            .unwrap();
+        // the rc file of the user's shell may print too
+        let output = lines.take();
+        assert!(output.contains(&"downloading".to_string()), "{:?}", output);
+        assert!(output.contains(&"[stderr] warning".to_string()), "{:?}", output);
+
+        let err = run_command_pipe_on_unix(
+            "seq 1 30; sleep 0.1; echo 'curl: (6) Could not resolve host' >&2; exit 3",
+            |_| {},
+        )
+        .await
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("failed with exit status: 3"), "{}", err);
+        assert!(err.ends_with("[stderr] curl: (6) Could not resolve host"), "{}", err);
+        assert!(!err.contains("\n11\n"), "{}", err);
+        assert!(err.contains("\n12\n"), "{}", err);
+
+        let options = CommandOptions {
+            shell: Some("sh".to_string()),
+            timeout: Some(Duration::from_secs(1)),
+            ..Default::default()
+        };
+        let mut output = vec![];
+        let err = run_command_streaming("echo started; sleep 5", &options, |line| output.push(line.to_string()))
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "`echo started; sleep 5` timed out after 1s");
+        assert_eq!(output, vec!["started"]);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_kill_process_group_on_timeout() {
+        let pid_path = env::temp_dir().join(format!("toolkit-test-process-group-{}", std::process::id()));
+        let options = CommandOptions {
+            shell: Some("sh".to_string()),
+            timeout: Some(Duration::from_secs(1)),
+            ..Default::default()
+        };
+        let program = format!("sleep 30 & echo $! > {}; wait", pid_path.display());
+        let err = run_command_streaming(&program, &options, |_| {}).await.unwrap_err();
+        assert!(err.downcast_ref::<CommandTimeoutError>().is_some(), "{}", err);
+
+        let pid = std::fs::read_to_string(&pid_path).unwrap().trim().to_string();
+        std::fs::remove_file(&pid_path).unwrap();
+        // the background `sleep` is killed with the shell which started it, a zombie waiting to be reaped is dead too
+        let is_dead = (0..50).any(|_| {
+            std::thread::sleep(Duration::from_millis(20));
+            match std::fs::read_to_string(format!("/proc/{}/stat", pid)) {
+                Ok(stat) => stat
+                    .rsplit(')')
+                    .next()
+                    .is_some_and(|rest| rest.trim_start().starts_with('Z')),
+                Err(_) => true,
+            }
+        });
+        assert!(is_dead, "process {} is still running", pid);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_run_command_on_unix() -> anyhow::Result<()> {
+        let output = run_command_on_unix("echo toolkit").await?;
+        assert!(output.status.success());
+        assert!(String::from_utf8_lossy(&output.stdout).ends_with("toolkit\n"));
+        Ok(())
     }
 }
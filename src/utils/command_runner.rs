@@ -0,0 +1,78 @@
+use anyhow::Result;
+use futures::future::BoxFuture;
+use std::process::{Output, Stdio};
+
+use super::command::{build_command, ensure_success, CommandTimeoutError, ProcessGroupGuard};
+use crate::{is_cmd_exists, run_command_interactive, run_command_streaming, CommandOptions};
+
+/// Runs the commands of the installations, so that the installers can be tested without touching the machine.
+pub trait CommandRunner: Send + Sync {
+    /// Runs the command in a shell and returns its output whatever its exit code.
+    fn run<'a>(&'a self, program: &'a str, options: &'a CommandOptions) -> BoxFuture<'a, Result<Output>>;
+
+    /// Runs the command in a shell and passes every line of its output to `on_line` as it is printed. Fails when the
+    /// command exits with a non-zero code.
+    fn run_streaming<'a>(
+        &'a self,
+        program: &'a str,
+        options: &'a CommandOptions,
+        on_line: &'a mut (dyn FnMut(&str) + Send),
+    ) -> BoxFuture<'a, Result<()>>;
+
+    /// Runs the command in a shell attached to a pseudo-terminal, so that the user can answer its prompts. Fails when the
+    /// command exits with a non-zero code.
+    fn run_interactive<'a>(&'a self, program: &'a str, options: &'a CommandOptions) -> BoxFuture<'a, Result<()>>;
+
+    /// Checks whether the command is on the `PATH` of the user's shell.
+    fn exists<'a>(&'a self, program: &'a str) -> BoxFuture<'a, Result<bool>>;
+}
+
+/// Runs the commands on the machine.
+#[derive(Debug, Default)]
+pub struct SystemCommandRunner;
+
+impl CommandRunner for SystemCommandRunner {
+    fn run<'a>(&'a self, program: &'a str, options: &'a CommandOptions) -> BoxFuture<'a, Result<Output>> {
+        Box::pin(async move {
+            let (shell, mut command) = build_command(program, options);
+            let child = command
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()
+                .map_err(|err| anyhow::anyhow!("Failed to run `{}` with {}. Error: {}", program, shell, err))?;
+            let mut process_group = ProcessGroupGuard::new(&child);
+            let output = child.wait_with_output();
+            let output = match options.timeout {
+                Some(timeout) => tokio::time::timeout(timeout, output)
+                    .await
+                    .map_err(|_| CommandTimeoutError::error(program, timeout))?,
+                None => output.await,
+            }
+            .map_err(|err| anyhow::anyhow!("Failed to run `{}` with {}. Error: {}", program, shell, err))?;
+            process_group.disarm();
+            Ok(output)
+        })
+    }
+
+    fn run_streaming<'a>(
+        &'a self,
+        program: &'a str,
+        options: &'a CommandOptions,
+        on_line: &'a mut (dyn FnMut(&str) + Send),
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(run_command_streaming(program, options, on_line))
+    }
+
+    fn run_interactive<'a>(&'a self, program: &'a str, options: &'a CommandOptions) -> BoxFuture<'a, Result<()>> {
+        Box::pin(run_command_interactive(program, options))
+    }
+
+    fn exists<'a>(&'a self, program: &'a str) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(is_cmd_exists(program))
+    }
+}
+
+/// Runs the command with the runner and fails when it exits with a non-zero code, with its stderr in the error.
+pub async fn run_checked(runner: &dyn CommandRunner, program: &str, options: &CommandOptions) -> Result<Output> {
+    ensure_success(program, runner.run(program, options).await?)
+}
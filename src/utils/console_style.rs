@@ -14,3 +14,4 @@ fn create_spinner_style() -> ProgressStyle {
 
 pub static SUCCESS_EMOJI: Emoji<'_, '_> = Emoji("✅", "");
 pub static ERROR_EMOJI: Emoji<'_, '_> = Emoji("❌", "");
+pub static WARNING_EMOJI: Emoji<'_, '_> = Emoji("⚠️ ", "");
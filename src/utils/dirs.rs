@@ -0,0 +1,19 @@
+use std::{env, path::PathBuf};
+
+/// The directory of the user configuration, `$XDG_CONFIG_HOME/toolkit` or `~/.config/toolkit`.
+pub fn get_config_dir() -> PathBuf {
+    get_xdg_dir("XDG_CONFIG_HOME", ".config").join("toolkit")
+}
+
+/// The directory of the cached files, `$XDG_CACHE_HOME/toolkit` or `~/.cache/toolkit`.
+pub fn get_cache_dir() -> PathBuf {
+    get_xdg_dir("XDG_CACHE_HOME", ".cache").join("toolkit")
+}
+
+// relative paths in the XDG variables are invalid and must be ignored
+fn get_xdg_dir(variable: &str, default_dir: &str) -> PathBuf {
+    env::var_os(variable)
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .unwrap_or_else(|| home::home_dir().unwrap_or_default().join(default_dir))
+}
@@ -2,10 +2,28 @@ use anyhow::Result;
 use futures_util::StreamExt;
 use regex::Regex;
 use reqwest::{header::HeaderValue, Client, Response, Url};
+use sha2::{Digest, Sha256};
 use std::{cmp::min, env, fs::File, io::Write, path::PathBuf};
 
-pub async fn download_file(url: &str, set_process_message: impl Fn(&str)) -> Result<PathBuf> {
-    let client = Client::new();
+/// A downloaded file with the url it was finally downloaded from after redirects, its sha256 checksum and size.
+#[derive(Debug)]
+pub struct DownloadedFile {
+    pub path: PathBuf,
+    pub url: String,
+    pub sha256: String,
+    pub size: u64,
+}
+
+pub async fn download_file(url: &str, set_process_message: impl Fn(&str)) -> Result<DownloadedFile> {
+    download_file_with_client(&Client::new(), url, set_process_message).await
+}
+
+/// Downloads the file with the client, e.g. one which refuses some redirects.
+pub async fn download_file_with_client(
+    client: &Client,
+    url: &str,
+    set_process_message: impl Fn(&str),
+) -> Result<DownloadedFile> {
     let response = client
         .get(url)
         .send()
@@ -15,11 +33,15 @@ pub async fn download_file(url: &str, set_process_message: impl Fn(&str)) -> Res
         .content_length()
         .ok_or(anyhow::anyhow!("Failed to get content length from '{}'", &url))?;
 
+    let final_url = response.url().to_string();
+
     let mut file_path = env::temp_dir();
     file_path.push(get_file_name_from_response(&response)?);
 
     let mut file = File::create(&file_path)?;
 
+    let mut hasher = Sha256::new();
+    let mut size: u64 = 0;
     let mut downloaded: u64 = 0;
     let mut stream = response.bytes_stream();
 
@@ -27,6 +49,8 @@ pub async fn download_file(url: &str, set_process_message: impl Fn(&str)) -> Res
         let chunk = item.map_err(|err| anyhow::anyhow!("Failed to get next item from stream. Error: {}", err))?;
         file.write_all(&chunk)
             .map_err(|err| anyhow::anyhow!("Failed to write to file '{:?}'. Error: {}", &file_path, err))?;
+        hasher.update(&chunk);
+        size += chunk.len() as u64;
         let new = min(downloaded + (chunk.len() as u64), total_size);
         downloaded = new;
 
@@ -40,7 +64,12 @@ pub async fn download_file(url: &str, set_process_message: impl Fn(&str)) -> Res
 
     set_process_message(&format!("Downloaded {} to {:?}", url, &file_path));
 
-    Ok(file_path)
+    Ok(DownloadedFile {
+        path: file_path,
+        url: final_url,
+        sha256: format!("{:x}", hasher.finalize()),
+        size,
+    })
 }
 
 fn get_file_name_from_response(response: &Response) -> Result<String> {
@@ -56,7 +85,7 @@ fn get_file_name_from_response(response: &Response) -> Result<String> {
 
 fn get_last_segment_from_url(url: &str) -> Result<String> {
     let url = Url::parse(url).map_err(|err| anyhow::anyhow!("Failed to parse url '{}'. Error: {}", url, err))?;
-    Ok(url.path_segments().unwrap().last().unwrap().to_string())
+    Ok(url.path_segments().unwrap().next_back().unwrap().to_string())
 }
 
 fn get_file_name_by_content_disposition(content_disposition: &HeaderValue, url: &str) -> Result<String> {
@@ -91,7 +120,7 @@ mod tests {
     async fn test_download_file() -> Result<()> {
         // Windows exe file
         let download_result = download_file("https://releases.arc.net/windows/ArcInstaller.exe", |_| {}).await?;
-        assert!(download_result.exists());
+        assert!(download_result.path.exists());
 
         Ok(())
     }
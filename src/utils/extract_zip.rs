@@ -1,20 +1,33 @@
 use anyhow::Result;
 use std::path::Path;
 
-use crate::run_command_on_unix;
+use crate::{quote_shell_arg, CommandOptions, CommandRunner};
 
-pub fn extract_zip<T: AsRef<Path>>(zip_path: T, extract_path: &str) -> Result<()> {
-    run_command_on_unix(&format!("unzip -o {} -d {}", zip_path.as_ref().display(), extract_path))?;
+pub async fn extract_zip<T: AsRef<Path>>(runner: &dyn CommandRunner, zip_path: T, extract_path: &str) -> Result<()> {
+    let program = format!(
+        "unzip -o {} -d {}",
+        quote_shell_arg(&zip_path.as_ref().to_string_lossy()),
+        quote_shell_arg(extract_path)
+    );
+    let output = runner.run(&program, &CommandOptions::default()).await?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "Failed to extract '{}' to '{}': {}",
+            zip_path.as_ref().display(),
+            extract_path,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
     Ok(())
 }
 
+#[cfg(target_os = "macos")]
 #[cfg(test)]
 mod tests {
     pub use super::*;
-    use crate::download_file;
+    use crate::{download_file, SystemCommandRunner};
     use std::fs;
 
-    #[cfg(target_os = "macos")]
     #[tokio::test]
     async fn test_extract_zip() -> Result<()> {
         let extract_path = "tmp/extract_zip_test";
@@ -23,8 +36,8 @@ mod tests {
         let zip_path = download_file(
             "https://vscode.download.prss.microsoft.com/dbazure/download/insider/5f78b58b57b7cf84d28d801fed6bb4a48f908601/VSCode-darwin-arm64.zip",
             |_| {},
-        ).await?;
-        extract_zip(&zip_path, extract_path)?;
+        ).await?.path;
+        extract_zip(&SystemCommandRunner, &zip_path, extract_path).await?;
 
         assert!(Path::new(extract_path).exists());
         let entries = fs::read_dir(extract_path)?.collect::<Result<Vec<_>, _>>()?;
@@ -0,0 +1,202 @@
+use anyhow::Result;
+use futures::future::BoxFuture;
+use std::{
+    collections::HashSet,
+    process::{ExitStatus, Output},
+    sync::Mutex,
+};
+
+use super::command::failed_with_output;
+use crate::{CommandOptions, CommandRunner};
+
+/// The scripted result of the commands run by a [`FakeCommandRunner`].
+#[derive(Debug, Clone, Default)]
+pub struct FakeOutput {
+    pub code: i32,
+    pub stdout: String,
+    pub stderr: String,
+    /// A command which exists once the command ran, e.g. the command installed by an install script.
+    pub installs: Option<String>,
+}
+
+impl FakeOutput {
+    pub fn success(stdout: &str) -> Self {
+        Self {
+            stdout: stdout.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn failure(code: i32, stderr: &str) -> Self {
+        Self {
+            code,
+            stderr: stderr.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn installing(mut self, command: &str) -> Self {
+        self.installs = Some(command.to_string());
+        self
+    }
+}
+
+/// Records the commands instead of running them and returns scripted outputs, for tests. A command without a script
+/// succeeds without output.
+#[derive(Debug, Default)]
+pub struct FakeCommandRunner {
+    outputs: Vec<(String, FakeOutput)>,
+    existing_commands: Mutex<HashSet<String>>,
+    commands: Mutex<Vec<String>>,
+    interactive_commands: Mutex<Vec<String>>,
+}
+
+impl FakeCommandRunner {
+    /// Scripts the output of the commands which contain `pattern`. The first matching script is used.
+    pub fn with_output(mut self, pattern: &str, output: FakeOutput) -> Self {
+        self.outputs.push((pattern.to_string(), output));
+        self
+    }
+
+    /// Makes [`CommandRunner::exists`] find the command.
+    pub fn with_existing_command(self, command: &str) -> Self {
+        self.existing_commands.lock().unwrap().insert(command.to_string());
+        self
+    }
+
+    /// Returns the commands run so far, in order.
+    pub fn commands(&self) -> Vec<String> {
+        self.commands.lock().unwrap().clone()
+    }
+
+    /// Returns the commands run in a pseudo-terminal so far, in order. They are in [`Self::commands`] too.
+    pub fn interactive_commands(&self) -> Vec<String> {
+        self.interactive_commands.lock().unwrap().clone()
+    }
+
+    fn record(&self, program: &str) -> FakeOutput {
+        self.commands.lock().unwrap().push(program.to_string());
+        let output = self
+            .outputs
+            .iter()
+            .find(|(pattern, _)| program.contains(pattern.as_str()))
+            .map(|(_, output)| output.clone())
+            .unwrap_or_default();
+        if let Some(command) = &output.installs {
+            self.existing_commands.lock().unwrap().insert(command.clone());
+        }
+        output
+    }
+}
+
+impl CommandRunner for FakeCommandRunner {
+    fn run<'a>(&'a self, program: &'a str, _options: &'a CommandOptions) -> BoxFuture<'a, Result<Output>> {
+        let output = self.record(program);
+        Box::pin(async move {
+            Ok(Output {
+                status: exit_status(output.code),
+                stdout: output.stdout.into_bytes(),
+                stderr: output.stderr.into_bytes(),
+            })
+        })
+    }
+
+    fn run_streaming<'a>(
+        &'a self,
+        program: &'a str,
+        _options: &'a CommandOptions,
+        on_line: &'a mut (dyn FnMut(&str) + Send),
+    ) -> BoxFuture<'a, Result<()>> {
+        let output = self.record(program);
+        Box::pin(async move {
+            let lines = output
+                .stdout
+                .lines()
+                .map(str::to_string)
+                .chain(output.stderr.lines().map(|line| format!("[stderr] {}", line)))
+                .collect::<Vec<_>>();
+            for line in &lines {
+                on_line(line);
+            }
+            let status = exit_status(output.code);
+            if !status.success() {
+                return Err(failed_with_output(program, status, lines));
+            }
+            Ok(())
+        })
+    }
+
+    fn run_interactive<'a>(&'a self, program: &'a str, _options: &'a CommandOptions) -> BoxFuture<'a, Result<()>> {
+        self.interactive_commands.lock().unwrap().push(program.to_string());
+        let output = self.record(program);
+        Box::pin(async move {
+            let status = exit_status(output.code);
+            if !status.success() {
+                return Err(anyhow::anyhow!("`{}` failed with {}", program, status));
+            }
+            Ok(())
+        })
+    }
+
+    fn exists<'a>(&'a self, program: &'a str) -> BoxFuture<'a, Result<bool>> {
+        let exists = self.existing_commands.lock().unwrap().contains(program);
+        Box::pin(async move { Ok(exists) })
+    }
+}
+
+fn exit_status(code: i32) -> ExitStatus {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        ExitStatus::from_raw(code << 8)
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::ExitStatusExt;
+        ExitStatus::from_raw(code as u32)
+    }
+}
+
+#[cfg(test)]
+mod test_fake_command_runner {
+    use super::*;
+    use crate::run_checked;
+
+    #[tokio::test]
+    async fn test_fake_command_runner() -> Result<()> {
+        let runner = FakeCommandRunner::default()
+            .with_output(
+                "install.sh",
+                FakeOutput::success("downloading\ninstalled").installing("fnm"),
+            )
+            .with_output("fnm --version", FakeOutput::success("fnm 1.37.1"))
+            .with_output("corepack", FakeOutput::failure(127, "corepack: command not found"));
+        let options = CommandOptions::default();
+
+        assert!(!runner.exists("fnm").await?);
+        let mut lines = vec![];
+        runner
+            .run_streaming("bash install.sh", &options, &mut |line| lines.push(line.to_string()))
+            .await?;
+        assert_eq!(lines, vec!["downloading", "installed"]);
+        assert!(runner.exists("fnm").await?);
+
+        let output = runner.run("fnm --version", &options).await?;
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "fnm 1.37.1");
+
+        let err = run_checked(&runner, "corepack enable", &options).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "`corepack enable` failed with {}: corepack: command not found",
+                exit_status(127)
+            )
+        );
+        assert_eq!(
+            runner.commands(),
+            vec!["bash install.sh", "fnm --version", "corepack enable"]
+        );
+        Ok(())
+    }
+}
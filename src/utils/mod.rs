@@ -1,9 +1,22 @@
 mod command;
+mod command_runner;
 mod console_style;
+mod dirs;
 mod download_file;
 mod extract_zip;
+#[cfg(any(test, feature = "test-util"))]
+mod fake_command_runner;
+mod pty;
 
-pub use command::{is_cmd_exists, run_command_on_unix, run_command_on_windows, run_command_pipe_on_unix};
+pub use command::{
+    is_cmd_exists, quote_shell_arg, run_command_on_unix, run_command_on_windows, run_command_pipe_on_unix,
+    run_command_streaming, run_shell_command, CommandOptions, CommandTimeoutError,
+};
+pub use command_runner::{run_checked, CommandRunner, SystemCommandRunner};
 pub use console_style::*;
-pub use download_file::download_file;
+pub use dirs::{get_cache_dir, get_config_dir};
+pub use download_file::{download_file, download_file_with_client, DownloadedFile};
 pub use extract_zip::extract_zip;
+#[cfg(any(test, feature = "test-util"))]
+pub use fake_command_runner::{FakeCommandRunner, FakeOutput};
+pub use pty::run_command_interactive;
@@ -0,0 +1,221 @@
+use anyhow::Result;
+use lazy_static::lazy_static;
+use portable_pty::{native_pty_system, CommandBuilder, PtySize};
+use std::{
+    env,
+    io::{self, IsTerminal, Read, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use super::command::{get_shell_invocation, CommandTimeoutError, ProcessGroupGuard};
+use crate::CommandOptions;
+
+/// How long the output printed by the processes left running by an interactive command is still copied once it
+/// exited.
+const OUTPUT_DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+lazy_static! {
+    /// The input of toolkit, read by a single thread for the whole run. A thread blocked on stdin cannot be stopped, so
+    /// a thread per command would swallow the first keys typed for the next one.
+    static ref STDIN: Mutex<Receiver<Vec<u8>>> = Mutex::new(spawn_stdin_reader());
+}
+
+/// Runs the command in a shell attached to a pseudo-terminal, so that an installer which needs a terminal shows its
+/// prompts. The output of the pseudo-terminal is copied to the stdout of toolkit and the input of toolkit to the
+/// pseudo-terminal, in raw mode when it is a terminal itself so that every key, e.g. Ctrl-C, reaches the command.
+/// Fails when the command exits with a non-zero code. The command and the processes it started are killed when it
+/// times out or when the returned future is dropped.
+pub async fn run_command_interactive(program: &str, options: &CommandOptions) -> Result<()> {
+    let (shell, args) = get_shell_invocation(program, options.shell.as_deref());
+    let (cols, rows) = crossterm::terminal::size().unwrap_or((80, 24));
+    let pair = native_pty_system()
+        .openpty(PtySize {
+            rows,
+            cols,
+            pixel_width: 0,
+            pixel_height: 0,
+        })
+        .map_err(|err| anyhow::anyhow!("Failed to open a pseudo-terminal for `{}`. Error: {}", program, err))?;
+    let mut command = CommandBuilder::new(&shell);
+    command.args(&args);
+    for (key, value) in &options.env {
+        command.env(key, value);
+    }
+    // the pseudo-terminal starts the command in the home directory by default
+    command.cwd(match &options.cwd {
+        Some(cwd) => cwd.clone(),
+        None => env::current_dir()?,
+    });
+    let mut child = pair
+        .slave
+        .spawn_command(command)
+        .map_err(|err| anyhow::anyhow!("Failed to run `{}` with {}. Error: {}", program, shell, err))?;
+    // the command leads a new session, and so a process group, in the pseudo-terminal
+    let mut process_group = ProcessGroupGuard::from_pid(child.process_id());
+    // the command holds the only other handle of the pseudo-terminal, so that its output ends when the command exits
+    drop(pair.slave);
+    let mut reader = pair.master.try_clone_reader()?;
+    let writer = pair.master.take_writer()?;
+
+    let _raw_mode = RawMode::enable();
+    let output = tokio::task::spawn_blocking(move || {
+        let mut stdout = io::stdout();
+        let mut buffer = [0; 4096];
+        // the read fails instead of returning 0 on linux once the command exited
+        while let Ok(count) = reader.read(&mut buffer) {
+            if count == 0 {
+                break;
+            }
+            let _ = stdout.write_all(&buffer[..count]);
+            let _ = stdout.flush();
+        }
+    });
+    let _input = InputForwarder::start(writer);
+
+    let wait = tokio::task::spawn_blocking(move || child.wait());
+    let status = match options.timeout {
+        Some(timeout) => tokio::time::timeout(timeout, wait)
+            .await
+            .map_err(|_| CommandTimeoutError::error(program, timeout))?,
+        None => wait.await,
+    }?
+    .map_err(|err| anyhow::anyhow!("Failed to run `{}` with {}. Error: {}", program, shell, err))?;
+    process_group.disarm();
+    let _ = tokio::time::timeout(OUTPUT_DRAIN_TIMEOUT, output).await;
+    if !status.success() {
+        return Err(anyhow::anyhow!("`{}` failed: {}", program, status));
+    }
+    Ok(())
+}
+
+fn spawn_stdin_reader() -> Receiver<Vec<u8>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut stdin = io::stdin();
+        let mut buffer = [0; 1024];
+        while let Ok(count) = stdin.read(&mut buffer) {
+            if count == 0 || sender.send(buffer[..count].to_vec()).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+/// Copies the input of toolkit to the pseudo-terminal of a command until it is dropped.
+struct InputForwarder {
+    stopped: Arc<AtomicBool>,
+}
+
+impl InputForwarder {
+    fn start(mut writer: Box<dyn Write + Send>) -> Self {
+        let stopped = Arc::new(AtomicBool::new(false));
+        let is_stopped = Arc::clone(&stopped);
+        thread::spawn(move || {
+            let stdin = STDIN.lock().unwrap();
+            // the keys typed before the command started were not meant for it
+            while stdin.try_recv().is_ok() {}
+            while !is_stopped.load(Ordering::Relaxed) {
+                match stdin.recv_timeout(Duration::from_millis(50)) {
+                    Ok(input) => {
+                        if writer.write_all(&input).and_then(|_| writer.flush()).is_err() {
+                            break;
+                        }
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    // the closed input of toolkit is passed on as an end of file, Ctrl-D
+                    Err(RecvTimeoutError::Disconnected) => {
+                        let _ = writer.write_all(&[4]).and_then(|_| writer.flush());
+                        break;
+                    }
+                }
+            }
+        });
+        Self { stopped }
+    }
+}
+
+impl Drop for InputForwarder {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Passes every key typed in the terminal of toolkit to the command until it is dropped.
+struct RawMode {
+    enabled: bool,
+}
+
+impl RawMode {
+    fn enable() -> Self {
+        Self {
+            enabled: io::stdin().is_terminal() && crossterm::terminal::enable_raw_mode().is_ok(),
+        }
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        if self.enabled {
+            let _ = crossterm::terminal::disable_raw_mode();
+        }
+    }
+}
+
+#[cfg(unix)]
+#[cfg(test)]
+mod test_pty {
+    use super::*;
+
+    fn sh(timeout: Option<u64>) -> CommandOptions {
+        CommandOptions {
+            shell: Some("sh".to_string()),
+            timeout: timeout.map(Duration::from_secs),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_command_interactive() {
+        // the command runs in a terminal even when toolkit does not
+        run_command_interactive("test -t 0 && test -t 1", &sh(None))
+            .await
+            .unwrap();
+
+        let err = run_command_interactive("exit 3", &sh(None)).await.unwrap_err();
+        assert_eq!(err.to_string(), "`exit 3` failed: Exited with code 3");
+
+        let err = run_command_interactive("sleep 30", &sh(Some(1))).await.unwrap_err();
+        assert_eq!(err.to_string(), "`sleep 30` timed out after 1s");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_kill_process_tree_on_timeout() {
+        let pid_path = env::temp_dir().join(format!("toolkit-test-pty-{}", std::process::id()));
+        let program = format!("sleep 30 & echo $! > {}; wait", pid_path.display());
+        let err = run_command_interactive(&program, &sh(Some(1))).await.unwrap_err();
+        assert!(err.downcast_ref::<CommandTimeoutError>().is_some(), "{}", err);
+
+        let pid = std::fs::read_to_string(&pid_path).unwrap().trim().to_string();
+        std::fs::remove_file(&pid_path).unwrap();
+        // a zombie waiting to be reaped is dead too
+        let is_dead = (0..50).any(|_| {
+            thread::sleep(Duration::from_millis(20));
+            match std::fs::read_to_string(format!("/proc/{}/stat", pid)) {
+                Ok(stat) => stat
+                    .rsplit(')')
+                    .next()
+                    .is_some_and(|rest| rest.trim_start().starts_with('Z')),
+                Err(_) => true,
+            }
+        });
+        assert!(is_dead, "process {} is still running", pid);
+    }
+}